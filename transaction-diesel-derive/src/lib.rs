@@ -0,0 +1,285 @@
+//! Derive macros for transaction-diesel
+//!
+//! Use them via the `derive` feature of `transaction-diesel`.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use syn::punctuated::Punctuated;
+use syn::{Data, DeriveInput, Error, Expr, Fields, Ident, Meta, Path, Token, Type};
+
+/// Generate CRUD functions returning `Transaction`s for a diesel model.
+///
+/// The following associated functions are generated, all of which have
+/// `Ctx = DieselContext<'a, Conn>` and `Err = diesel::result::Error`.
+///
+/// | function                | `Item`         |
+/// |-------------------------|----------------|
+/// | `create(new)`           | `Self`         |
+/// | `find(id)`              | `Option<Self>` |
+/// | `find_many(ids)`        | `Vec<Self>`    |
+/// | `update(self)`          | `Option<Self>` |
+/// | `delete(id)`            | `bool`         |
+/// | `exists(id)`            | `bool`         |
+/// | `count()`               | `i64`          |
+///
+/// `create` takes any `Insertable` value such as `NewUser` and returns the
+/// inserted row, so the backend must support `RETURNING`. `update` finds the
+/// updated row again, so it works on every backend.
+/// The model must implement `Queryable` for the table and `&Self` must
+/// implement `AsChangeset` (both given by the diesel derives).
+///
+/// The connection type is given by `#[tx_repository(conn = ...)]`.
+/// The table and the primary key are taken from `#[diesel(table_name = ..., primary_key(...))]`
+/// and can be overridden by `#[tx_repository(table = ..., pk = ...)]`.
+/// The primary key defaults to `id`. Composite primary keys are not supported.
+/// `find`, `find_many`, `exists` and `count` are `ReadOnly`, so they can be run
+/// on read replicas.
+///
+/// ```ignore
+/// #[derive(Queryable, AsChangeset, TxRepository)]
+/// #[diesel(table_name = users)]
+/// #[tx_repository(conn = PgConnection)]
+/// pub struct User {
+///     pub id: i64,
+///     pub name: String,
+/// }
+///
+/// let tx = User::find(id).and_then(|user| User::delete(id));
+/// ```
+#[proc_macro_derive(TxRepository, attributes(tx_repository))]
+pub fn derive_tx_repository(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match tx_repository(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+struct Options {
+    table: Option<Path>,
+    conn: Option<Type>,
+    pk: Option<Ident>,
+}
+
+fn options(input: &DeriveInput) -> syn::Result<Options> {
+    let mut opts = Options {
+        table: None,
+        conn: None,
+        pk: None,
+    };
+    // read diesel's attributes first so that ours override them
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("diesel")) {
+        let metas = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+        for meta in metas {
+            match meta {
+                Meta::NameValue(ref nv) if nv.path.is_ident("table_name") => {
+                    if let Expr::Path(ref p) = nv.value {
+                        opts.table = Some(p.path.clone());
+                    }
+                }
+                Meta::List(ref list) if list.path.is_ident("primary_key") => {
+                    let keys =
+                        list.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)?;
+                    if keys.len() != 1 {
+                        return Err(Error::new_spanned(
+                            list,
+                            "TxRepository does not support composite primary keys",
+                        ));
+                    }
+                    opts.pk = keys.into_iter().next();
+                }
+                _ => (),
+            }
+        }
+    }
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("tx_repository")) {
+        attr.parse_nested_meta(|meta| if meta.path.is_ident("table") {
+            opts.table = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("conn") {
+            opts.conn = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("pk") {
+            opts.pk = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("expected `table`, `conn` or `pk`"))
+        })?;
+    }
+    Ok(opts)
+}
+
+fn tx_repository(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let opts = options(input)?;
+    let table = opts.table.ok_or_else(|| {
+        Error::new(
+            Span::call_site(),
+            "specify the table by `#[diesel(table_name = ...)]` or `#[tx_repository(table = ...)]`",
+        )
+    })?;
+    let conn = opts.conn.ok_or_else(|| {
+        Error::new(
+            Span::call_site(),
+            "specify the connection type by `#[tx_repository(conn = ...)]`",
+        )
+    })?;
+    let pk = opts.pk.unwrap_or_else(|| Ident::new("id", Span::call_site()));
+
+    let fields = match input.data {
+        Data::Struct(ref s) => {
+            match s.fields {
+                Fields::Named(ref fields) => fields,
+                _ => {
+                    return Err(Error::new_spanned(
+                        input,
+                        "TxRepository can only be derived for structs with named fields",
+                    ))
+                }
+            }
+        }
+        _ => {
+            return Err(Error::new_spanned(
+                input,
+                "TxRepository can only be derived for structs",
+            ))
+        }
+    };
+    let pk_ty = fields
+        .named
+        .iter()
+        .find(|f| f.ident.as_ref() == Some(&pk))
+        .map(|f| &f.ty)
+        .ok_or_else(|| {
+            Error::new_spanned(&pk, format!("no field named `{}` is found", pk))
+        })?;
+
+    let name = &input.ident;
+    let vis = &input.vis;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Insert a row and return it
+            #vis fn create<'__tx, __N>(new: __N)
+                -> impl ::transaction_diesel::__private::Transaction<
+                    Ctx = ::transaction_diesel::DieselContext<'__tx, #conn>,
+                    Item = Self,
+                    Err = ::transaction_diesel::__private::result::Error,
+                > + '__tx
+            where
+                __N: ::transaction_diesel::__private::Insertable<#table::table> + Clone + '__tx,
+                ::transaction_diesel::__private::query_builder::InsertStatement<#table::table, __N::Values>:
+                    ::transaction_diesel::__private::query_dsl::LoadQuery<'__tx, #conn, Self>,
+            {
+                use ::transaction_diesel::__private::RunQueryDsl;
+                ::transaction_diesel::with_conn(move |cn: &mut #conn| {
+                    ::transaction_diesel::__private::insert_into(#table::table)
+                        .values(new.clone())
+                        .get_result(cn)
+                })
+            }
+
+            /// Find the row by the primary key
+            #vis fn find<'__tx>(id: #pk_ty)
+                -> impl ::transaction_diesel::__private::Transaction<
+                    Ctx = ::transaction_diesel::DieselContext<'__tx, #conn>,
+                    Item = Option<Self>,
+                    Err = ::transaction_diesel::__private::result::Error,
+                > + ::transaction_diesel::ReadOnly + '__tx
+            {
+                use ::transaction_diesel::__private::{OptionalExtension, QueryDsl, RunQueryDsl};
+                ::transaction_diesel::with_conn_ro(move |cn: &mut #conn| {
+                    #table::table.find(id.clone()).get_result(cn).optional()
+                })
+            }
+
+            /// Find the rows by the primary keys
+            #vis fn find_many<'__tx>(ids: Vec<#pk_ty>)
+                -> impl ::transaction_diesel::__private::Transaction<
+                    Ctx = ::transaction_diesel::DieselContext<'__tx, #conn>,
+                    Item = Vec<Self>,
+                    Err = ::transaction_diesel::__private::result::Error,
+                > + ::transaction_diesel::ReadOnly + '__tx
+            {
+                use ::transaction_diesel::__private::{ExpressionMethods, QueryDsl, RunQueryDsl};
+                ::transaction_diesel::with_conn_ro(move |cn: &mut #conn| {
+                    #table::table.filter(#table::#pk.eq_any(ids.clone())).load(cn)
+                })
+            }
+
+            /// Update the row with the same primary key and return the updated row
+            #vis fn update<'__tx>(self)
+                -> impl ::transaction_diesel::__private::Transaction<
+                    Ctx = ::transaction_diesel::DieselContext<'__tx, #conn>,
+                    Item = Option<Self>,
+                    Err = ::transaction_diesel::__private::result::Error,
+                > + '__tx
+            where
+                Self: '__tx,
+            {
+                use ::transaction_diesel::__private::{OptionalExtension, QueryDsl, RunQueryDsl};
+                ::transaction_diesel::with_conn(move |cn: &mut #conn| {
+                    // found again rather than returned, as not every backend has `RETURNING`
+                    let updated = ::transaction_diesel::__private::update(#table::table.find(self.#pk.clone()))
+                        .set(&self)
+                        .execute(cn)?;
+                    if updated == 0 {
+                        return Ok(None);
+                    }
+                    #table::table.find(self.#pk.clone()).get_result(cn).optional()
+                })
+            }
+
+            /// Delete the row by the primary key. Returns whether the row existed
+            #vis fn delete<'__tx>(id: #pk_ty)
+                -> impl ::transaction_diesel::__private::Transaction<
+                    Ctx = ::transaction_diesel::DieselContext<'__tx, #conn>,
+                    Item = bool,
+                    Err = ::transaction_diesel::__private::result::Error,
+                > + '__tx
+            {
+                use ::transaction_diesel::__private::{QueryDsl, RunQueryDsl};
+                ::transaction_diesel::with_conn(move |cn: &mut #conn| {
+                    ::transaction_diesel::__private::delete(#table::table.find(id.clone()))
+                        .execute(cn)
+                        .map(|n| n > 0)
+                })
+            }
+
+            /// Whether the row with the primary key exists
+            #vis fn exists<'__tx>(id: #pk_ty)
+                -> impl ::transaction_diesel::__private::Transaction<
+                    Ctx = ::transaction_diesel::DieselContext<'__tx, #conn>,
+                    Item = bool,
+                    Err = ::transaction_diesel::__private::result::Error,
+                > + ::transaction_diesel::ReadOnly + '__tx
+            {
+                use ::transaction_diesel::__private::{QueryDsl, RunQueryDsl};
+                ::transaction_diesel::with_conn_ro(move |cn: &mut #conn| {
+                    ::transaction_diesel::__private::select(::transaction_diesel::__private::dsl::exists(#table::table.find(id.clone())))
+                        .get_result(cn)
+                })
+            }
+
+            /// The number of the rows in the table
+            #vis fn count<'__tx>()
+                -> impl ::transaction_diesel::__private::Transaction<
+                    Ctx = ::transaction_diesel::DieselContext<'__tx, #conn>,
+                    Item = i64,
+                    Err = ::transaction_diesel::__private::result::Error,
+                > + ::transaction_diesel::ReadOnly + '__tx
+            {
+                use ::transaction_diesel::__private::{QueryDsl, RunQueryDsl};
+                ::transaction_diesel::with_conn_ro(move |cn: &mut #conn| {
+                    #table::table.count().get_result(cn)
+                })
+            }
+        }
+    })
+}
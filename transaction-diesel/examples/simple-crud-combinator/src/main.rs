@@ -1,17 +1,15 @@
 #[macro_use]
 extern crate diesel;
-#[macro_use]
-extern crate diesel_codegen;
 extern crate dotenv;
 extern crate transaction;
 extern crate transaction_diesel;
 
 mod schema;
 mod model;
-mod db;
 
 use transaction::prelude::*;
 use diesel::pg::PgConnection;
+use model::*;
 
 pub fn establish_connection() -> PgConnection {
     use dotenv::dotenv;
@@ -25,47 +23,33 @@ pub fn establish_connection() -> PgConnection {
 }
 
 fn main() {
-    let conn = establish_connection();
+    let mut conn = establish_connection();
     // composed computation of DB operations
-    let tx = db::create_user("keen")
+    let tx = User::create(NewUser { name: "keen" })
     // Transactions can be sequenced using `and_then`
-        .and_then(move |user| {
-        println!("created user: {:?}", user);
-        db::update_user(user.id, "KeenS")
-                // to pass values by move, you need to inject them by `join`
-            .join(ok(user))
-            .and_then(|(res, user)| match res {
-                None => {
-                    println!("user not found");
-                    // when you branch and return different `Transaction`s it is an error. Some operation is needed.
-                    // One option is boxing all the transactions returning from all the branches.
-                    // Another option is using `branch` API. Use `first` in one branch and `second` in the other branch.
-                    ok(()).branch().first()
-                }
-                Some(()) => db::find_user(user.id)
-                .and_then(move |maybe_updated_user| {
-                    match maybe_updated_user {
-                        None => {
-                            println!("user not found");
-                            ok(()).branch().first()
-                        },
-                        Some(updated_user) => {
-                            println!("updated user: {:?}", updated_user);
-                            db::delete_user(updated_user.id)
-                                .map(|res| match res {
-                                    None => {
-                                        println!("user not found");
-                                    },
-                                    Some(()) => ()
-                                })
-                                .branch().second()
-                        }
-                    }
-                }).branch().second(),
-
-            })
-    });
+        .and_then(|user| {
+            println!("created user: {:?}", user);
+            User { name: "KeenS".to_string(), ..user }.update()
+        })
+        .and_then(|res| match res {
+            None => {
+                println!("user not found");
+                // when you branch and return different `Transaction`s it is an error. Some operation is needed.
+                // One option is boxing all the transactions returning from all the branches.
+                // Another option is using `branch` API. Use `first` in one branch and `second` in the other branch.
+                ok(()).branch().first()
+            }
+            Some(updated_user) => {
+                println!("updated user: {:?}", updated_user);
+                User::delete(updated_user.id)
+                    .map(|deleted| if !deleted {
+                        println!("user not found");
+                    })
+                    .branch()
+                    .second()
+            }
+        });
 
     // to run the composed computation, use `transaction_diesel::run`.
-    transaction_diesel::run(&conn, tx).unwrap()
+    transaction_diesel::run(&mut conn, tx).unwrap()
 }
@@ -1,7 +1,14 @@
+use diesel::pg::PgConnection;
+use transaction_diesel::TxRepository;
+
 use schema::*;
 
+// `TxRepository` generates `create`, `find`, `update`, `delete` and so on
+// that return `Transaction`s.
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Default, Hash)]
-#[derive(Queryable)]
+#[derive(Queryable, AsChangeset, TxRepository)]
+#[diesel(table_name = users)]
+#[tx_repository(conn = PgConnection)]
 pub struct User {
     pub id: i64,
     pub name: String,
@@ -9,7 +16,7 @@ pub struct User {
 
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Default, Hash)]
 #[derive(Insertable)]
-#[table_name = "users"]
+#[diesel(table_name = users)]
 pub struct NewUser<'a> {
     pub name: &'a str,
 }
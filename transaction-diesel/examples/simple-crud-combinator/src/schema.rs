@@ -1 +1,6 @@
-infer_schema!("dotenv:DATABASE_URL");
+table! {
+    users (id) {
+        id -> Int8,
+        name -> Varchar,
+    }
+}
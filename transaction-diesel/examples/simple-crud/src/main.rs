@@ -1,7 +1,5 @@
 #[macro_use]
 extern crate diesel;
-#[macro_use]
-extern crate diesel_codegen;
 extern crate dotenv;
 extern crate transaction;
 extern crate transaction_diesel;
@@ -26,7 +24,7 @@ pub fn establish_connection() -> PgConnection {
 }
 
 fn main() {
-    let conn = establish_connection();
+    let mut conn = establish_connection();
     // composed computation of DB operations
     // you can get transaction context using `with_ctx`
     let tx = with_ctx(|ctx| -> Result<(), Error> {
@@ -60,5 +58,5 @@ fn main() {
         Ok(())
     });
     // to run the composed computation, use `transaction_diesel::run`.
-    transaction_diesel::run(&conn, tx).unwrap()
+    transaction_diesel::run(&mut conn, tx).unwrap()
 }
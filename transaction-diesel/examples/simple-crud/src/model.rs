@@ -9,7 +9,7 @@ pub struct User {
 
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Default, Hash)]
 #[derive(Insertable)]
-#[table_name = "users"]
+#[diesel(table_name = users)]
 pub struct NewUser<'a> {
     pub name: &'a str,
 }
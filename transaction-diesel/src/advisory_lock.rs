@@ -0,0 +1,103 @@
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+use diesel;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Bool};
+use transaction::Transaction;
+
+use PgContext;
+
+/// The error of `try_advisory_xact_lock` when the lock is held by another session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockNotAcquired {
+    /// The key of the advisory lock
+    pub key: i64,
+}
+
+impl fmt::Display for LockNotAcquired {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "advisory lock {} is not acquired", self.key)
+    }
+}
+
+impl Error for LockNotAcquired {}
+
+/// Take a transaction level advisory lock, waiting for it if necessary.
+/// The lock is released at the end of the transaction.
+pub fn advisory_xact_lock<'a, E>(key: i64) -> AdvisoryXactLock<'a, E>
+where
+    E: From<diesel::result::Error>,
+{
+    AdvisoryXactLock {
+        key: key,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `advisory_xact_lock`
+#[derive(Debug)]
+#[must_use]
+pub struct AdvisoryXactLock<'a, E> {
+    key: i64,
+    _phantom: PhantomData<fn() -> (&'a (), E)>,
+}
+
+impl<'a, E> Transaction for AdvisoryXactLock<'a, E>
+where
+    E: From<diesel::result::Error>,
+{
+    type Ctx = PgContext<'a>;
+    type Item = ();
+    type Err = E;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        // a lock on a replica does not exclude the writers on the primary
+        ctx.write()?;
+        diesel::sql_query("SELECT pg_advisory_xact_lock($1)")
+            .bind::<BigInt, _>(self.key)
+            .execute(ctx.conn())?;
+        Ok(())
+    }
+}
+
+/// Try to take a transaction level advisory lock without waiting.
+/// Fails with `LockNotAcquired` if the lock is held by another session.
+pub fn try_advisory_xact_lock<'a, E>(key: i64) -> TryAdvisoryXactLock<'a, E>
+where
+    E: From<diesel::result::Error> + From<LockNotAcquired>,
+{
+    TryAdvisoryXactLock {
+        key: key,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `try_advisory_xact_lock`
+#[derive(Debug)]
+#[must_use]
+pub struct TryAdvisoryXactLock<'a, E> {
+    key: i64,
+    _phantom: PhantomData<fn() -> (&'a (), E)>,
+}
+
+impl<'a, E> Transaction for TryAdvisoryXactLock<'a, E>
+where
+    E: From<diesel::result::Error> + From<LockNotAcquired>,
+{
+    type Ctx = PgContext<'a>;
+    type Item = ();
+    type Err = E;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.write()?;
+        let lock = sql::<Bool>("pg_try_advisory_xact_lock(")
+            .bind::<BigInt, _>(self.key)
+            .sql(")");
+        if diesel::select(lock).get_result::<bool>(ctx.conn())? {
+            Ok(())
+        } else {
+            Err(LockNotAcquired { key: self.key }.into())
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+use transaction::Transaction;
+
+use {DieselContext, ReadOnly};
+
+/// A map of per-run values, such as the current user, the tenant or the
+/// request id, held by `DieselContext`. A value is stored for each type, so
+/// wrap common types in newtypes.
+/// The values are cloned into the context of `requires_new`.
+///
+/// ```ignore
+/// #[derive(Clone)]
+/// struct CurrentUser(i64);
+///
+/// let mut ext = Extensions::new();
+/// ext.insert(CurrentUser(id));
+/// run_with_extensions(&mut cn, ext, with_conn_ext(|cn, ext| {
+///     let CurrentUser(id) = *ext.get::<CurrentUser>().unwrap();
+///     posts::table.filter(posts::user_id.eq(id)).load::<Post>(cn)
+/// }))
+/// ```
+#[derive(Default, Clone)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn Extension>>,
+}
+
+trait Extension {
+    fn clone_box(&self) -> Box<dyn Extension>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Clone + 'static> Extension for T {
+    fn clone_box(&self) -> Box<dyn Extension> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Clone for Box<dyn Extension> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+impl Extensions {
+    /// Create an empty map
+    pub fn new() -> Self {
+        Extensions::default()
+    }
+
+    /// Insert the value, returning the previous value of the type
+    pub fn insert<T: Clone + 'static>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|prev| prev.into_any().downcast().ok())
+            .map(|prev| *prev)
+    }
+
+    /// Get the value of the type
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|v| (**v).as_any().downcast_ref())
+    }
+
+    /// Get the value of the type mutably
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|v| (**v).as_any_mut().downcast_mut())
+    }
+
+    /// Remove the value of the type
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|v| v.into_any().downcast().ok())
+            .map(|v| *v)
+    }
+
+    /// Whether a value of the type is stored
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Remove all the values
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
+
+/// Receive the connection and the extensions from the executing transaction
+/// and perform computation.
+pub fn with_conn_ext<'a, Conn, F, T, E>(f: F) -> WithConnExt<'a, Conn, F>
+where
+    F: Fn(&mut Conn, &mut Extensions) -> Result<T, E>,
+{
+    WithConnExt {
+        f: f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `with_conn_ext`
+#[derive(Debug)]
+pub struct WithConnExt<'a, Conn: 'a, F> {
+    f: F,
+    _phantom: PhantomData<fn() -> &'a Conn>,
+}
+
+impl<'a, Conn, T, E, F> Transaction for WithConnExt<'a, Conn, F>
+where
+    F: Fn(&mut Conn, &mut Extensions) -> Result<T, E>,
+    E: From<diesel::result::Error>,
+{
+    type Ctx = DieselContext<'a, Conn>;
+    type Item = T;
+    type Err = E;
+    fn run(&self, ctx: &mut DieselContext<'a, Conn>) -> Result<Self::Item, Self::Err> {
+        ctx.write()?;
+        (self.f)(&mut **ctx.conn, &mut ctx.extensions)
+    }
+}
+
+/// Same as `with_conn_ext` but the computation only reads the database, so
+/// the transaction is `ReadOnly` like `with_conn_ro`.
+pub fn with_conn_ext_ro<'a, Conn, F, T, E>(f: F) -> WithConnExtRo<'a, Conn, F>
+where
+    F: Fn(&mut Conn, &mut Extensions) -> Result<T, E>,
+{
+    WithConnExtRo {
+        f: f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `with_conn_ext_ro`
+#[derive(Debug)]
+pub struct WithConnExtRo<'a, Conn: 'a, F> {
+    f: F,
+    _phantom: PhantomData<fn() -> &'a Conn>,
+}
+
+impl<'a, Conn, T, E, F> Transaction for WithConnExtRo<'a, Conn, F>
+where
+    F: Fn(&mut Conn, &mut Extensions) -> Result<T, E>,
+{
+    type Ctx = DieselContext<'a, Conn>;
+    type Item = T;
+    type Err = E;
+    fn run(&self, ctx: &mut DieselContext<'a, Conn>) -> Result<Self::Item, Self::Err> {
+        (self.f)(&mut **ctx.conn, &mut ctx.extensions)
+    }
+}
+
+impl<'a, Conn, F> ReadOnly for WithConnExtRo<'a, Conn, F> {}
@@ -1,60 +1,344 @@
 //! A transaction runner for diesel
+//!
+//! The backends are selected by the cargo features `postgres`, `mysql` and
+//! `sqlite`. The runners work with any `diesel::Connection`, and the features
+//! add context aliases such as `PgContext`.
+//! With the `r2d2` feature, transactions can be run on a connection pool.
+//! The runners honour the propagation set by `required`, `requires_new`,
+//! `nested` and `never`. `requires_new` needs a new connection, so it is
+//! supported only by the pooled runners.
+//! With the `derive` feature, `#[derive(TxRepository)]` is available to
+//! generate CRUD transactions for models.
+//! Models implementing `Entity` can be managed by a unit of work: `load`,
+//! `persist` and `remove` track them during the run, and the changes are
+//! flushed before the commit.
+//! Callbacks registered by `on_commit` and `on_rollback` are called after the
+//! runners commit or roll back the transaction.
+
+// struct literals spell out the field names, as in `f: f`
+#![allow(clippy::redundant_field_names)]
 
 extern crate diesel;
 extern crate transaction;
+#[cfg(feature = "derive")]
+#[allow(unused_imports)]
+#[macro_use]
+extern crate transaction_diesel_derive;
 use transaction::*;
 use std::marker::PhantomData;
+use std::ops::DerefMut;
+use std::rc::Rc;
+use diesel::connection::{Connection, TransactionManager};
+use unit_of_work::UnitOfWork;
+
+#[doc(hidden)]
+pub mod __private {
+    // used by the macros and the derives
+    pub use diesel::{delete, insert_into, select, update};
+    pub use diesel::{dsl, query_builder, query_dsl, result};
+    pub use diesel::{ExpressionMethods, Insertable, OptionalExtension, QueryDsl, RunQueryDsl};
+    pub use transaction::Transaction;
+}
+
+#[macro_use]
+mod lock;
+#[cfg(feature = "postgres")]
+mod advisory_lock;
+mod extensions;
+#[cfg(feature = "postgres")]
+mod notify;
+mod optimistic;
+#[cfg(feature = "r2d2")]
+mod pool;
+mod read_only;
+#[cfg(feature = "postgres")]
+mod settings;
+mod unit_of_work;
+
+#[cfg(feature = "derive")]
+pub use transaction_diesel_derive::*;
+#[cfg(feature = "postgres")]
+pub use advisory_lock::*;
+pub use extensions::*;
+#[cfg(feature = "postgres")]
+pub use notify::*;
+pub use optimistic::*;
+#[cfg(feature = "r2d2")]
+pub use pool::*;
+pub use read_only::*;
+#[cfg(feature = "postgres")]
+pub use settings::*;
+pub use unit_of_work::*;
 
 /// run the given function insed a transaction using the given connection.
-pub fn run<'a, Cn, T, E, Tx>(cn: &'a Cn, tx: Tx) -> Result<T, E>
+pub fn run<'a, Cn, T, E, Tx>(cn: &'a mut Cn, tx: Tx) -> Result<T, E>
 where
-    Cn: diesel::Connection,
+    Cn: Connection,
     E: From<diesel::result::Error>,
     Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
 {
-    cn.clone().transaction(
-        || tx.run(&mut DieselContext::new(cn)),
-    )
+    backend::run(&mut DieselContext::new(cn), tx)
+}
+
+/// Same as `run` but the context has the given extensions.
+pub fn run_with_extensions<'a, Cn, T, E, Tx>(cn: &'a mut Cn, extensions: Extensions, tx: Tx) -> Result<T, E>
+where
+    Cn: Connection,
+    E: From<diesel::result::Error>,
+    Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
+{
+    let mut ctx = DieselContext::new(cn);
+    ctx.extensions = extensions;
+    backend::run(&mut ctx, tx)
 }
 
 /// run the given function insed a transaction using the given connection but do not commit it.
 /// Panics if the given function returns an Err.
 /// This is usefull for testing
-pub fn test_run<'a, Cn, T, E, Tx>(cn: &'a Cn, tx: Tx) -> T
+pub fn test_run<'a, Cn, T, E, Tx>(cn: &'a mut Cn, tx: Tx) -> T
 where
-    Cn: diesel::Connection,
+    Cn: Connection,
     E: From<diesel::result::Error>,
     Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
 {
-    cn.clone().test_transaction(
-        || tx.run(&mut DieselContext::new(cn)),
-    )
+    backend::test_run(&mut DieselContext::new(cn), tx)
+}
+
+/// run the given function insed a transaction using the given connection and
+/// roll it back, returning the result that would be committed.
+/// The hooks of `on_rollback` are called.
+pub fn dry_run<'a, Cn, T, E, Tx>(cn: &'a mut Cn, tx: Tx) -> Result<T, E>
+where
+    Cn: Connection,
+    E: From<diesel::result::Error>,
+    Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
+{
+    backend::dry_run(&mut DieselContext::new(cn), tx)
 }
 
 /// diesel transaction object.
 pub struct DieselContext<'a, Cn: 'a> {
-    conn: &'a Cn,
+    // either a borrowed connection or a connection checked out from a pool
+    conn: BoxConn<'a, Cn>,
+    // run on a read replica
+    read_only: bool,
+    // a transaction that may write is run on a read replica
+    requires_primary: bool,
+    // opens a new connection for `requires_new`
+    connect: Option<Rc<Connect<'a, Cn>>>,
+    // run at the beginning of each transaction, given by `run_with_settings`
+    settings: Option<Rc<Settings<'a, Cn>>>,
+    // the results of `memo` and `memo_by`
+    memo: MemoCache,
+    // the changes to `SharedCache`s, applied after the commit
+    cache_log: CacheLog,
+    // the entities of `load` and `persist`, flushed before the commit
+    unit_of_work: UnitOfWork<Cn>,
+    // per-run values given by the caller
+    extensions: Extensions,
+    // the callbacks of `on_commit` and `on_rollback`
+    hooks: Hooks,
     _phantom: PhantomData<()>,
 }
 
+type BoxConn<'a, Cn> = Box<dyn DerefMut<Target = Cn> + 'a>;
+
+type Connect<'a, Cn> = dyn Fn() -> Result<BoxConn<'a, Cn>, PropagationError> + 'a;
+
+type Settings<'a, Cn> = dyn Fn(&mut Cn) -> diesel::QueryResult<()> + 'a;
+
 impl<'a, Cn> DieselContext<'a, Cn> {
     // never pub this function
-    fn new(conn: &'a Cn) -> Self {
+    fn new<C>(conn: C) -> Self
+    where
+        C: DerefMut<Target = Cn> + 'a,
+    {
+        DieselContext::boxed(Box::new(conn))
+    }
+
+    // every context is made here
+    fn boxed(conn: BoxConn<'a, Cn>) -> Self {
         DieselContext {
             conn: conn,
+            read_only: false,
+            requires_primary: false,
+            connect: None,
+            settings: None,
+            memo: MemoCache::new(),
+            cache_log: CacheLog::new(),
+            unit_of_work: UnitOfWork::new(),
+            extensions: Extensions::new(),
+            hooks: Hooks::new(),
             _phantom: PhantomData,
         }
     }
 
-    fn conn(&self) -> &'a Cn {
-        &self.conn
+    fn conn(&mut self) -> &mut Cn {
+        &mut self.conn
+    }
+
+    /// The per-run values, seeded by `run_with_extensions`
+    pub fn extensions(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    // the running transaction may write, so the memoized results may be stale
+    fn requires_write(&mut self) {
+        self.memo.clear();
+        if self.read_only {
+            self.requires_primary = true;
+        }
+    }
+
+    // the running transaction writes right now, so on a read replica it stops
+    // before writing and is rerun on the primary
+    fn write(&mut self) -> diesel::QueryResult<()> {
+        self.requires_write();
+        if self.read_only {
+            Err(diesel::result::Error::RollbackTransaction)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn flush_unit_of_work(&mut self) -> diesel::QueryResult<()> {
+        self.unit_of_work.flush(&mut **self.conn)
     }
 }
 
+// Savepoints are nested transactions of diesel's `TransactionManager`.
+impl<'a, Cn> TxBackend for DieselContext<'a, Cn>
+where
+    Cn: Connection,
+{
+    type Ctx = Self;
+    type Error = diesel::result::Error;
+
+    fn ctx(&mut self) -> &mut Self {
+        self
+    }
+
+    fn in_transaction(&mut self) -> Result<bool, Self::Error> {
+        Cn::TransactionManager::transaction_manager_status_mut(self.conn())
+            .transaction_depth()
+            .map(|depth| depth.is_some())
+    }
+
+    fn new_backend(&mut self) -> Result<Self, PropagationError> {
+        if self.read_only {
+            // the new transaction may write
+            self.requires_primary = true;
+            return Err(PropagationError::Unsupported);
+        }
+        let connect = match self.connect {
+            Some(ref connect) => connect.clone(),
+            None => return Err(PropagationError::Unsupported),
+        };
+        let mut ctx = DieselContext::boxed(connect()?);
+        ctx.extensions = self.extensions.clone();
+        ctx.settings = self.settings.clone();
+        ctx.connect = Some(connect);
+        Ok(ctx)
+    }
+
+    fn begin(&mut self) -> Result<(), Self::Error> {
+        Cn::TransactionManager::begin_transaction(self.conn())?;
+        if let Some(settings) = self.settings.clone() {
+            if let Err(e) = settings(self.conn()) {
+                self.rollback()?;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), Self::Error> {
+        if let Err(e) = self.flush_unit_of_work() {
+            self.rollback()?;
+            return Err(e);
+        }
+        Cn::TransactionManager::commit_transaction(self.conn())?;
+        self.cache_log.commit();
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> Result<(), Self::Error> {
+        self.memo.clear();
+        self.unit_of_work.clear();
+        self.cache_log.rollback();
+        match Cn::TransactionManager::rollback_transaction(self.conn()) {
+            // the transaction is already gone with the connection
+            Err(diesel::result::Error::BrokenTransactionManager) => Ok(()),
+            r => r,
+        }
+    }
+
+    fn savepoint(&mut self) -> Result<(), Self::Error> {
+        // keep the changes made before the savepoint
+        self.flush_unit_of_work()?;
+        Cn::TransactionManager::begin_transaction(self.conn())?;
+        self.unit_of_work.savepoint();
+        self.cache_log.savepoint();
+        Ok(())
+    }
+
+    fn release(&mut self) -> Result<(), Self::Error> {
+        Cn::TransactionManager::commit_transaction(self.conn())?;
+        self.unit_of_work.release();
+        self.cache_log.release();
+        Ok(())
+    }
+
+    fn rollback_to_savepoint(&mut self) -> Result<(), Self::Error> {
+        self.memo.clear();
+        self.unit_of_work.rollback_to_savepoint();
+        self.cache_log.rollback_to_savepoint();
+        Cn::TransactionManager::rollback_transaction(self.conn())
+    }
+
+    fn hooks_mut(&mut self) -> Option<&mut Hooks> {
+        Some(&mut self.hooks)
+    }
+}
+
+impl<'a, Cn> MemoCtx for DieselContext<'a, Cn> {
+    fn memo_cache(&mut self) -> &mut MemoCache {
+        &mut self.memo
+    }
+}
+
+impl<'a, Cn> CacheCtx for DieselContext<'a, Cn> {
+    fn cache_log(&mut self) -> &mut CacheLog {
+        &mut self.cache_log
+    }
+}
+
+impl<'a, Cn> HookCtx for DieselContext<'a, Cn> {
+    fn hooks(&mut self) -> &mut Hooks {
+        &mut self.hooks
+    }
+}
+
+/// The context of the transactions run on Postgres
+#[cfg(feature = "postgres")]
+pub type PgContext<'a> = DieselContext<'a, diesel::pg::PgConnection>;
+
+/// The context of the transactions run on MySQL
+#[cfg(feature = "mysql")]
+pub type MysqlContext<'a> = DieselContext<'a, diesel::mysql::MysqlConnection>;
+
+/// The context of the transactions run on SQLite
+#[cfg(feature = "sqlite")]
+pub type SqliteContext<'a> = DieselContext<'a, diesel::sqlite::SqliteConnection>;
+
 /// Receive the connection from the executing transaction and perform computation.
+/// On a read replica of `run_routed`, the function is not called and the
+/// whole transaction is rerun on the primary.
+/// The results of `memo` and `memo_by` are cleared before the function is
+/// called, as it may change them.
 pub fn with_conn<'a, Conn, F, T, E>(f: F) -> WithConn<'a, Conn, F>
 where
-    F: Fn(&'a Conn) -> Result<T, E>,
+    F: Fn(&mut Conn) -> Result<T, E>,
 {
     WithConn {
         f: f,
@@ -66,17 +350,19 @@ where
 #[derive(Debug)]
 pub struct WithConn<'a, Conn: 'a, F> {
     f: F,
-    _phantom: PhantomData<&'a Conn>,
+    _phantom: PhantomData<fn() -> &'a Conn>,
 }
 
 impl<'a, Conn, T, E, F> Transaction for WithConn<'a, Conn, F>
 where
-    F: Fn(&'a Conn) -> Result<T, E>,
+    F: Fn(&mut Conn) -> Result<T, E>,
+    E: From<diesel::result::Error>,
 {
     type Ctx = DieselContext<'a, Conn>;
     type Item = T;
     type Err = E;
     fn run(&self, ctx: &mut DieselContext<'a, Conn>) -> Result<Self::Item, Self::Err> {
+        ctx.write()?;
         (self.f)(ctx.conn())
     }
 }
@@ -0,0 +1,21 @@
+/// Select a row with `FOR UPDATE` and return it if any.
+/// The row is locked until the end of the transaction.
+/// This is a macro rather than a function because diesel does not allow
+/// generic code to add locking clauses.
+///
+/// ```ignore
+/// lock_row_for_update!(users::table.find(id))
+///     .and_then(|user: Option<User>| ...)
+/// ```
+#[macro_export]
+macro_rules! lock_row_for_update {
+    ($query:expr) => {{
+        let query = $query;
+        $crate::with_conn(move |cn| {
+            let query = $crate::__private::QueryDsl::for_update(query.clone());
+            let row = $crate::__private::RunQueryDsl::get_result(query, cn);
+            $crate::__private::OptionalExtension::optional(row)
+                .map_err(::std::convert::From::from)
+        })
+    }};
+}
@@ -0,0 +1,188 @@
+use std::marker::PhantomData;
+#[cfg(feature = "r2d2")]
+use std::collections::VecDeque;
+#[cfg(feature = "r2d2")]
+use std::thread;
+#[cfg(feature = "r2d2")]
+use std::time::Duration;
+
+use diesel;
+#[cfg(feature = "r2d2")]
+use diesel::pg::{PgConnection, PgNotification};
+use diesel::prelude::*;
+#[cfg(feature = "r2d2")]
+use diesel::r2d2::{ConnectionManager, Pool, PoolError};
+use diesel::sql_types::Text;
+use transaction::Transaction;
+
+use PgContext;
+#[cfg(feature = "r2d2")]
+use run_pooled;
+
+/// Send a notification to the channel, as `NOTIFY`.
+/// Postgres delivers it to the listeners only when the transaction is
+/// committed, and drops it when rolled back.
+pub fn notify<'a, C, P, E>(channel: C, payload: P) -> Notify<'a, E>
+where
+    C: Into<String>,
+    P: Into<String>,
+    E: From<diesel::result::Error>,
+{
+    Notify {
+        channel: channel.into(),
+        payload: payload.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `notify`
+#[derive(Debug)]
+#[must_use]
+pub struct Notify<'a, E> {
+    channel: String,
+    payload: String,
+    _phantom: PhantomData<fn() -> (&'a (), E)>,
+}
+
+impl<'a, E> Transaction for Notify<'a, E>
+where
+    E: From<diesel::result::Error>,
+{
+    type Ctx = PgContext<'a>;
+    type Item = ();
+    type Err = E;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.write()?;
+        diesel::sql_query("SELECT pg_notify($1, $2)")
+            .bind::<Text, _>(&self.channel)
+            .bind::<Text, _>(&self.payload)
+            .execute(ctx.conn())?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "r2d2")]
+type Handler<E> = Box<dyn Fn(&PgNotification, &Pool<ConnectionManager<PgConnection>>) -> Result<(), E>>;
+
+#[cfg(feature = "r2d2")]
+type DeadLetter<E> = Box<dyn Fn(&PgNotification, E)>;
+
+/// Receives notifications on a dedicated connection and dispatches them to
+/// the handlers of the channels. The transactions made by the handlers are
+/// run on connections checked out from the pool.
+///
+/// ```ignore
+/// let mut listener = Listener::new(PgConnection::establish(url)?, pool);
+/// listener.on("user_created", |n| {
+///     let id = n.payload.parse().unwrap();
+///     send_welcome_mail(id)
+/// })?;
+/// listener.dead_letter(3, |n, e| eprintln!("gave up {:?}: {:?}", n, e));
+/// listener.serve(Duration::from_millis(100))?;
+/// ```
+#[cfg(feature = "r2d2")]
+pub struct Listener<E> {
+    conn: PgConnection,
+    pool: Pool<ConnectionManager<PgConnection>>,
+    handlers: Vec<(String, Handler<E>)>,
+    // the number of the retries and the callback of the given up notifications
+    dead_letter: Option<(usize, DeadLetter<E>)>,
+    // received but not dispatched yet, with the index of the next handler
+    // and the number of its failures
+    pending: VecDeque<(PgNotification, usize, usize)>,
+}
+
+#[cfg(feature = "r2d2")]
+impl<E> Listener<E>
+where
+    E: From<diesel::result::Error> + From<PoolError>,
+{
+    /// Create a listener receiving notifications on `conn`. Do not use the
+    /// connection for anything else, as the transactions on it delay the
+    /// notifications.
+    pub fn new(conn: PgConnection, pool: Pool<ConnectionManager<PgConnection>>) -> Self {
+        Listener {
+            conn: conn,
+            pool: pool,
+            handlers: Vec::new(),
+            dead_letter: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Listen to the channel and run the transaction made by `f` for each
+    /// notification on it. Handlers of the same channel run in the order of
+    /// registration.
+    pub fn on<F, Tx>(&mut self, channel: &str, f: F) -> Result<(), diesel::result::Error>
+    where
+        F: Fn(&PgNotification) -> Tx + 'static,
+        Tx: Transaction<Ctx = PgContext<'static>, Item = (), Err = E>,
+    {
+        if self.handlers.iter().all(|h| h.0 != channel) {
+            let sql = format!("LISTEN \"{}\"", channel.replace('"', "\"\""));
+            diesel::sql_query(sql).execute(&mut self.conn)?;
+        }
+        self.handlers.push((
+            channel.to_string(),
+            Box::new(move |n, pool| run_pooled(pool, f(n))),
+        ));
+        Ok(())
+    }
+
+    /// Give up a notification after its handler fails `retries` more times,
+    /// passing the notification and the last error to `f`, e.g. to log it.
+    /// The rest of the handlers are run for it as usual.
+    pub fn dead_letter<F>(&mut self, retries: usize, f: F)
+    where
+        F: Fn(&PgNotification, E) + 'static,
+    {
+        self.dead_letter = Some((retries, Box::new(f)));
+    }
+
+    /// Dispatch the notifications received so far without waiting.
+    /// Returns the number of the dispatched notifications. If a handler
+    /// fails, the notification is kept and the next call dispatches it again
+    /// from the failed handler, followed by the rest of the notifications,
+    /// unless it is given up by `dead_letter`.
+    pub fn poll(&mut self) -> Result<usize, E> {
+        for n in self.conn.notifications_iter() {
+            self.pending.push_back((n?, 0, 0));
+        }
+        let mut dispatched = 0;
+        while let Some((n, next, failures)) = self.pending.pop_front() {
+            // the failures of the handler at `next`
+            let mut failures = failures;
+            for (i, (channel, handler)) in self.handlers.iter().enumerate().skip(next) {
+                if *channel != n.channel {
+                    continue;
+                }
+                if let Err(e) = handler(&n, &self.pool) {
+                    match self.dead_letter {
+                        Some((retries, ref f)) if retries <= failures => f(&n, e),
+                        _ => {
+                            self.pending.push_front((n, i, failures + 1));
+                            return Err(e);
+                        }
+                    }
+                }
+                failures = 0;
+            }
+            dispatched += 1;
+        }
+        Ok(dispatched)
+    }
+
+    /// Dispatch notifications, checking new ones every `interval` when idle.
+    /// Returns the first error, or with `dead_letter`, retries the failed
+    /// notification every `interval` until it is given up.
+    pub fn serve(&mut self, interval: Duration) -> Result<(), E> {
+        loop {
+            match self.poll() {
+                Ok(0) => thread::sleep(interval),
+                Ok(_) => (),
+                Err(_) if self.dead_letter.is_some() => thread::sleep(interval),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
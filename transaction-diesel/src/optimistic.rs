@@ -0,0 +1,142 @@
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Add;
+
+use diesel;
+use diesel::associations::HasTable;
+use diesel::dsl::{CountStar, Eq, Filter, Find, Select};
+use diesel::expression::{AsExpression, TypedExpressionType};
+use diesel::prelude::*;
+use diesel::query_builder::{AsChangeset, AsQuery, IntoUpdateTarget, QueryFragment, QueryId,
+                            UpdateStatement};
+use diesel::query_dsl::methods::{FilterDsl, FindDsl, SelectDsl};
+use diesel::query_dsl::LoadQuery;
+use diesel::sql_types::SqlType;
+use transaction::Transaction;
+
+use DieselContext;
+
+/// Tables that have a version column for optimistic concurrency control.
+///
+/// ```ignore
+/// impl Versioned for users::table {
+///     type Version = users::version;
+/// }
+/// ```
+pub trait Versioned: Table {
+    /// The version column, incremented on every `update_if_version`
+    type Version: Column<Table = Self> + Default + ExpressionMethods;
+}
+
+/// The error of `update_if_version` when the row is not of the expected version,
+/// i.e. it is updated by another transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict;
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the row is updated by another transaction")
+    }
+}
+
+impl Error for Conflict {}
+
+/// Update the row of `id` with `changes` and increment its version if the
+/// version is `expected_version`. Fails with `Conflict` if the row has another
+/// version, and with `diesel::result::Error::NotFound` if there is no such row.
+/// Compose the read-modify-write with `retry_if` to re-run it on conflicts.
+/// Run each attempt in `nested` so that a failed attempt is rolled back to
+/// its savepoint before the next one.
+///
+/// ```ignore
+/// retry_if(3, |e: &Error| e.is_conflict(), move |_| {
+///     find_user(id)
+///         .and_then(move |user| {
+///             update_if_version(users::table, id, user.version, users::name.eq(name))
+///         })
+///         .nested()
+/// })
+/// ```
+pub fn update_if_version<'a, Conn, T, K, V, C, E>(
+    table: T,
+    id: K,
+    expected_version: V,
+    changes: C,
+) -> UpdateIfVersion<'a, Conn, T, K, V, C, E>
+where
+    E: From<diesel::result::Error> + From<Conflict>,
+{
+    UpdateIfVersion {
+        table: table,
+        id: id,
+        expected_version: expected_version,
+        changes: changes,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `update_if_version`
+#[derive(Debug)]
+#[must_use]
+pub struct UpdateIfVersion<'a, Conn: 'a, T, K, V, C, E> {
+    table: T,
+    id: K,
+    expected_version: V,
+    changes: C,
+    _phantom: PhantomData<fn() -> (&'a Conn, E)>,
+}
+
+impl<'a, Conn, T, K, V, C, E> Transaction for UpdateIfVersion<'a, Conn, T, K, V, C, E>
+where
+    Conn: Connection,
+    E: From<diesel::result::Error> + From<Conflict>,
+    T: Versioned + FindDsl<K> + Clone,
+    K: Clone,
+    C: Clone,
+    V: Clone + Add<Output = V> + From<u8> + AsExpression<<T::Version as Expression>::SqlType>,
+    <T::Version as Expression>::SqlType: SqlType + TypedExpressionType,
+    Find<T, K>: FilterDsl<Eq<T::Version, V>>,
+    Filter<Find<T, K>, Eq<T::Version, V>>: IntoUpdateTarget,
+    (C, Eq<T::Version, V>): AsChangeset<
+        Target = <Filter<Find<T, K>, Eq<T::Version, V>> as HasTable>::Table,
+    >,
+    UpdateStatement<
+        <Filter<Find<T, K>, Eq<T::Version, V>> as HasTable>::Table,
+        <Filter<Find<T, K>, Eq<T::Version, V>> as IntoUpdateTarget>::WhereClause,
+        <(C, Eq<T::Version, V>) as AsChangeset>::Changeset,
+    >: AsQuery + QueryFragment<Conn::Backend> + QueryId,
+    Find<T, K>: SelectDsl<CountStar>,
+    Select<Find<T, K>, CountStar>: for<'q> LoadQuery<'q, Conn, i64>,
+{
+    type Ctx = DieselContext<'a, Conn>;
+    type Item = ();
+    type Err = E;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.requires_write();
+        let UpdateIfVersion {
+            ref table,
+            ref id,
+            ref expected_version,
+            ref changes,
+            ..
+        } = *self;
+        let next_version = expected_version.clone() + V::from(1);
+        let target = table.clone().find(id.clone()).filter(
+            T::Version::default().eq(expected_version.clone()),
+        );
+        let updated = diesel::update(target)
+            .set((changes.clone(), T::Version::default().eq(next_version)))
+            .execute(ctx.conn())?;
+        if 0 < updated {
+            return Ok(());
+        }
+        let rows: i64 = SelectDsl::select(table.clone().find(id.clone()), diesel::dsl::count_star())
+            .get_result(ctx.conn())?;
+        if 0 < rows {
+            Err(Conflict.into())
+        } else {
+            Err(diesel::result::Error::NotFound.into())
+        }
+    }
+}
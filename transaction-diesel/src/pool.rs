@@ -0,0 +1,220 @@
+use std::rc::Rc;
+
+use diesel;
+use diesel::connection::{Connection, TransactionManager};
+#[cfg(feature = "sqlite")]
+use diesel::connection::SimpleConnection;
+use diesel::r2d2::{ConnectionManager, Pool, PoolError, R2D2Connection};
+use diesel::result::{DatabaseErrorKind, Error};
+use transaction::backend::finish;
+use transaction::{Propagation, PropagationError, Transaction, TxBackend};
+
+use {BoxConn, Connect, DieselContext, Extensions, ReadOnly, Settings};
+
+/// check out a connection from the pool and run the given transaction on it.
+/// The connection is returned to the pool after the transaction finishes.
+pub fn run_pooled<'a, Cn, T, E, Tx>(pool: &Pool<ConnectionManager<Cn>>, tx: Tx) -> Result<T, E>
+where
+    Cn: R2D2Connection + 'static,
+    E: From<diesel::result::Error> + From<PoolError>,
+    Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
+{
+    run_pooled_with_retry(pool, 0, tx)
+}
+
+/// Same as `run_pooled` but if the checked out connection is broken, checks
+/// out another one up to `retries` times. As the connection is found broken
+/// when beginning the transaction, no statement of the transaction has run
+/// when it is retried.
+/// Transactions with a propagation, such as `required`, begin the transaction
+/// by themselves, so for them the connection is pinged before running them
+/// while retries remain.
+pub fn run_pooled_with_retry<'a, Cn, T, E, Tx>(
+    pool: &Pool<ConnectionManager<Cn>>,
+    retries: usize,
+    tx: Tx,
+) -> Result<T, E>
+where
+    Cn: R2D2Connection + 'static,
+    E: From<diesel::result::Error> + From<PoolError>,
+    Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
+{
+    run_pooled_in(pool, retries, &Extensions::new(), None, tx)
+}
+
+/// Same as `run_pooled` but the context has the given extensions.
+pub fn run_pooled_with_extensions<'a, Cn, T, E, Tx>(
+    pool: &Pool<ConnectionManager<Cn>>,
+    extensions: Extensions,
+    tx: Tx,
+) -> Result<T, E>
+where
+    Cn: R2D2Connection + 'static,
+    E: From<diesel::result::Error> + From<PoolError>,
+    Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
+{
+    run_pooled_in(pool, 0, &extensions, None, tx)
+}
+
+pub(crate) fn run_pooled_in<'a, Cn, T, E, Tx>(
+    pool: &Pool<ConnectionManager<Cn>>,
+    retries: usize,
+    extensions: &Extensions,
+    settings: Option<Rc<Settings<'a, Cn>>>,
+    tx: Tx,
+) -> Result<T, E>
+where
+    Cn: R2D2Connection + 'static,
+    E: From<diesel::result::Error> + From<PoolError>,
+    Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
+{
+    let mut retries = retries;
+    loop {
+        let mut ctx = DieselContext::new(pool.get()?);
+        ctx.connect = Some(connect(pool));
+        ctx.extensions = extensions.clone();
+        ctx.settings = settings.clone();
+        if tx.propagation().is_some() {
+            if 0 < retries && !is_usable(ctx.conn()) {
+                retries -= 1;
+                continue;
+            }
+            return tx.run(&mut ctx);
+        }
+        match ctx.begin() {
+            Ok(()) => {
+                let ret = tx.run(&mut ctx);
+                return finish(&mut ctx, ret);
+            }
+            Err(ref e) if 0 < retries && is_broken(e) => retries -= 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// run the given read only transaction on a connection checked out from the
+/// pool of read replicas.
+pub fn run_read_only<'a, Cn, T, E, Tx>(replica: &Pool<ConnectionManager<Cn>>, tx: Tx) -> Result<T, E>
+where
+    Cn: R2D2Connection + 'static,
+    E: From<diesel::result::Error> + From<PoolError>,
+    Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E> + ReadOnly,
+{
+    run_pooled(replica, tx)
+}
+
+/// run the given transaction on the replica pool if it turns out to be read
+/// only, otherwise on the primary pool.
+/// Use `run_read_only` if the transaction is statically known as `ReadOnly`.
+/// This function first runs the transaction on a replica, and if it reaches a
+/// `with_conn`, rollbacks it and runs the whole transaction again on the
+/// primary. Thus the transaction may be run twice.
+/// The transaction on the replica begins as read only, so the writes that are
+/// not guarded by `with_conn` fail instead of landing on the replica.
+/// `required` and `nested` transactions join the transaction on the replica,
+/// and `requires_new` ones are routed to the primary. `never` transactions
+/// run on the primary as they cannot be made read only.
+pub fn run_routed<'a, Cn, T, E, Tx>(
+    primary: &Pool<ConnectionManager<Cn>>,
+    replica: &Pool<ConnectionManager<Cn>>,
+    tx: Tx,
+) -> Result<T, E>
+where
+    Cn: R2D2Connection + ReadOnlyConnection + 'static,
+    E: From<diesel::result::Error> + From<PoolError>,
+    Tx: Transaction<Ctx = DieselContext<'a, Cn>, Item = T, Err = E>,
+{
+    // the writes outside of transactions cannot be rolled back
+    if tx.propagation() == Some(Propagation::Never) {
+        return run_pooled(primary, tx);
+    }
+    {
+        let mut ctx = ReadOnlyContext(DieselContext::new(replica.get()?));
+        ctx.0.read_only = true;
+        Cn::begin_read_only(ctx.0.conn())?;
+        let ret = tx.run(&mut ctx.0);
+        let ret = if ctx.0.requires_primary {
+            ctx.0.rollback().err().map(|e| Err(e.into()))
+        } else {
+            Some(finish(&mut ctx.0, ret))
+        };
+        if let Some(ret) = ret {
+            return ret;
+        }
+    }
+    run_pooled(primary, tx)
+}
+
+// resets the replica connection however the run ends, even by a panic
+struct ReadOnlyContext<'a, Cn: ReadOnlyConnection + 'a>(DieselContext<'a, Cn>);
+
+impl<'a, Cn: ReadOnlyConnection> Drop for ReadOnlyContext<'a, Cn> {
+    fn drop(&mut self) {
+        let conn = self.0.conn();
+        if Cn::end_read_only(conn).is_err() {
+            // the pool drops the broken connection instead of reusing it
+            Cn::TransactionManager::transaction_manager_status_mut(conn).set_in_error();
+        }
+    }
+}
+
+/// Connections that can begin read only transactions, used by `run_routed`
+pub trait ReadOnlyConnection: Connection {
+    /// begin a transaction in which writes fail
+    fn begin_read_only(&mut self) -> diesel::QueryResult<()>;
+    /// reset the connection after the read only transaction finished, or
+    /// after `begin_read_only` failed
+    fn end_read_only(&mut self) -> diesel::QueryResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl ReadOnlyConnection for diesel::pg::PgConnection {
+    fn begin_read_only(&mut self) -> diesel::QueryResult<()> {
+        diesel::connection::AnsiTransactionManager::begin_transaction_sql(self, "BEGIN TRANSACTION READ ONLY")
+    }
+}
+
+#[cfg(feature = "mysql")]
+impl ReadOnlyConnection for diesel::mysql::MysqlConnection {
+    fn begin_read_only(&mut self) -> diesel::QueryResult<()> {
+        diesel::connection::AnsiTransactionManager::begin_transaction_sql(self, "START TRANSACTION READ ONLY")
+    }
+}
+
+// SQLite has no read only transactions, so the connection is made read only
+// while the transaction runs
+#[cfg(feature = "sqlite")]
+impl ReadOnlyConnection for diesel::sqlite::SqliteConnection {
+    fn begin_read_only(&mut self) -> diesel::QueryResult<()> {
+        self.batch_execute("PRAGMA query_only = ON")?;
+        diesel::connection::AnsiTransactionManager::begin_transaction_sql(self, "BEGIN")
+    }
+    fn end_read_only(&mut self) -> diesel::QueryResult<()> {
+        self.batch_execute("PRAGMA query_only = OFF")
+    }
+}
+
+// check out new connections from the pool for `requires_new`
+fn connect<'a, Cn>(pool: &Pool<ConnectionManager<Cn>>) -> Rc<Connect<'a, Cn>>
+where
+    Cn: R2D2Connection + 'static,
+{
+    let pool = pool.clone();
+    Rc::new(move || match pool.get() {
+        Ok(conn) => Ok(Box::new(conn) as BoxConn<Cn>),
+        Err(e) => Err(PropagationError::Connect(Box::new(e))),
+    })
+}
+
+fn is_broken(e: &Error) -> bool {
+    matches!(
+        *e,
+        Error::DatabaseError(DatabaseErrorKind::ClosedConnection, _) | Error::BrokenTransactionManager
+    )
+}
+
+fn is_usable<Cn: R2D2Connection>(conn: &mut Cn) -> bool {
+    !conn.is_broken() && conn.ping().is_ok()
+}
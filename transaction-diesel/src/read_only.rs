@@ -0,0 +1,133 @@
+use std::marker::PhantomData;
+
+use transaction::*;
+
+use DieselContext;
+
+/// A marker of transactions that only read the database.
+/// Transactions composed only of `with_conn_ro` and the combinators of
+/// `transaction` are `ReadOnly`, and can be run on a read replica by
+/// `run_read_only`.
+/// Note that transactions using `with_conn` or `with_ctx` are never
+/// `ReadOnly` because they may write.
+pub trait ReadOnly {}
+
+/// Receive the connection from the executing transaction and perform
+/// computation that only reads the database.
+pub fn with_conn_ro<'a, Conn, F, T, E>(f: F) -> WithConnRo<'a, Conn, F>
+where
+    F: Fn(&mut Conn) -> Result<T, E>,
+{
+    WithConnRo {
+        f: f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `with_conn_ro`
+#[derive(Debug)]
+pub struct WithConnRo<'a, Conn: 'a, F> {
+    f: F,
+    _phantom: PhantomData<fn() -> &'a Conn>,
+}
+
+impl<'a, Conn, T, E, F> Transaction for WithConnRo<'a, Conn, F>
+where
+    F: Fn(&mut Conn) -> Result<T, E>,
+{
+    type Ctx = DieselContext<'a, Conn>;
+    type Item = T;
+    type Err = E;
+    fn run(&self, ctx: &mut DieselContext<'a, Conn>) -> Result<Self::Item, Self::Err> {
+        (self.f)(ctx.conn())
+    }
+}
+
+impl<'a, Conn, F> ReadOnly for WithConnRo<'a, Conn, F> {}
+
+impl<Ctx, T, E> ReadOnly for TxOk<Ctx, T, E> {}
+impl<Ctx, T, E> ReadOnly for TxErr<Ctx, T, E> {}
+impl<Ctx, T, E> ReadOnly for TxResult<Ctx, T, E> {}
+impl<Ctx, F> ReadOnly for Lazy<Ctx, F> {}
+
+impl<Tx: ?Sized + ReadOnly> ReadOnly for Box<Tx> {}
+impl<Tx: ?Sized + ReadOnly> ReadOnly for &Tx {}
+
+impl<Tx: ReadOnly, F> ReadOnly for Map<Tx, F> {}
+impl<Tx: ReadOnly, F> ReadOnly for MapErr<Tx, F> {}
+impl<Tx: ReadOnly, E> ReadOnly for ErrInto<Tx, E> {}
+impl<Tx: ReadOnly> ReadOnly for Context<Tx> {}
+impl<Tx: ReadOnly> ReadOnly for Named<Tx> {}
+impl<Tx: ReadOnly> ReadOnly for Memo<Tx> {}
+impl<Tx: ReadOnly, K> ReadOnly for MemoBy<Tx, K> {}
+// the cache is changed after the commit, not in the database
+impl<K, V, Tx: ReadOnly> ReadOnly for Cached<K, V, Tx> {}
+impl<Tx: ReadOnly, T, F> ReadOnly for Abort<Tx, T, F> {}
+impl<Tx: ReadOnly, F, B> ReadOnly for TryAbort<Tx, F, B> {}
+impl<Tx: ReadOnly, T, F> ReadOnly for Recover<Tx, T, F> {}
+impl<Tx: ReadOnly, F, B> ReadOnly for TryRecover<Tx, F, B> {}
+
+impl<Tx1, F, Tx2> ReadOnly for Then<Tx1, F, Tx2>
+where
+    Tx1: Transaction + ReadOnly,
+    Tx2: IntoTransaction<Tx1::Ctx>,
+    Tx2::Tx: ReadOnly,
+{
+}
+
+impl<Tx1, F, Tx2> ReadOnly for AndThen<Tx1, F, Tx2>
+where
+    Tx1: Transaction + ReadOnly,
+    Tx2: IntoTransaction<Tx1::Ctx>,
+    Tx2::Tx: ReadOnly,
+{
+}
+
+impl<Tx1, F, Tx2> ReadOnly for OrElse<Tx1, F, Tx2>
+where
+    Tx1: Transaction + ReadOnly,
+    Tx2: IntoTransaction<Tx1::Ctx>,
+    Tx2::Tx: ReadOnly,
+{
+}
+
+impl<Tx1: ReadOnly, Tx2: ReadOnly> ReadOnly for Join<Tx1, Tx2> {}
+impl<Tx1: ReadOnly, Tx2: ReadOnly, Tx3: ReadOnly> ReadOnly for Join3<Tx1, Tx2, Tx3> {}
+impl<Tx1: ReadOnly, Tx2: ReadOnly, Tx3: ReadOnly, Tx4: ReadOnly> ReadOnly
+    for Join4<Tx1, Tx2, Tx3, Tx4> {
+}
+impl<Tx: ReadOnly> ReadOnly for JoinAll<Tx> {}
+
+impl<Tx1: ReadOnly, Tx2: ReadOnly> ReadOnly for Branch<Tx1, Tx2> {}
+impl<Tx1: ReadOnly, Tx2: ReadOnly, Tx3: ReadOnly> ReadOnly for Branch3<Tx1, Tx2, Tx3> {}
+impl<Tx1: ReadOnly, Tx2: ReadOnly, Tx3: ReadOnly, Tx4: ReadOnly> ReadOnly
+    for Branch4<Tx1, Tx2, Tx3, Tx4> {
+}
+
+impl<Ctx, F, A> ReadOnly for LoopFn<Ctx, F, A>
+where
+    A: IntoTransaction<Ctx>,
+    A::Tx: ReadOnly,
+{
+}
+
+impl<Ctx, F, Tx> ReadOnly for Repeat<Ctx, F, Tx>
+where
+    Tx: IntoTransaction<Ctx>,
+    Tx::Tx: ReadOnly,
+{
+}
+
+impl<Ctx, F, Tx> ReadOnly for Retry<Ctx, F, Tx>
+where
+    Tx: IntoTransaction<Ctx>,
+    Tx::Tx: ReadOnly,
+{
+}
+
+impl<Ctx, P, F, Tx> ReadOnly for RetryIf<Ctx, P, F, Tx>
+where
+    Tx: IntoTransaction<Ctx>,
+    Tx::Tx: ReadOnly,
+{
+}
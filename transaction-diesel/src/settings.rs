@@ -0,0 +1,140 @@
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use diesel;
+#[cfg(feature = "r2d2")]
+use diesel::r2d2::{ConnectionManager, Pool, PoolError};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use transaction::backend;
+use transaction::prelude::*;
+
+use {DieselContext, PgContext, Settings};
+
+/// run the given transaction with the session settings, e.g. the keys of
+/// row-level security policies. The settings are set as `set_local` at the
+/// beginning of each transaction, including the ones begun by `required`,
+/// `nested` and `requires_new`, and end with it.
+///
+/// ```ignore
+/// run_with_settings(&mut cn, &[("app.tenant_id", "42")], find_posts())
+/// ```
+pub fn run_with_settings<'a, T, E, Tx>(
+    cn: &'a mut PgConnection,
+    settings: &[(&str, &str)],
+    tx: Tx,
+) -> Result<T, E>
+where
+    E: From<diesel::result::Error>,
+    Tx: Transaction<Ctx = PgContext<'a>, Item = T, Err = E>,
+{
+    let mut ctx = DieselContext::new(cn);
+    ctx.settings = Some(set_configs(settings));
+    backend::run(&mut ctx, tx)
+}
+
+/// Same as `run_with_settings` but on a connection checked out from the pool,
+/// as `run_pooled`.
+#[cfg(feature = "r2d2")]
+pub fn run_pooled_with_settings<'a, T, E, Tx>(
+    pool: &Pool<ConnectionManager<PgConnection>>,
+    settings: &[(&str, &str)],
+    tx: Tx,
+) -> Result<T, E>
+where
+    E: From<diesel::result::Error> + From<PoolError>,
+    Tx: Transaction<Ctx = PgContext<'a>, Item = T, Err = E>,
+{
+    ::pool::run_pooled_in(pool, 0, &::Extensions::new(), Some(set_configs(settings)), tx)
+}
+
+fn set_configs<'a>(settings: &[(&str, &str)]) -> Rc<Settings<'a, PgConnection>> {
+    let settings: Vec<(String, String)> = settings
+        .iter()
+        .map(|&(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    Rc::new(move |cn: &mut PgConnection| {
+        for (name, value) in &settings {
+            set_config(cn, name, value)?;
+        }
+        Ok(())
+    })
+}
+
+// set the parameter until the end of the transaction
+fn set_config(cn: &mut PgConnection, name: &str, value: &str) -> diesel::QueryResult<()> {
+    diesel::sql_query("SELECT set_config($1, $2, true)")
+        .bind::<Text, _>(name)
+        .bind::<Text, _>(value)
+        .execute(cn)?;
+    Ok(())
+}
+
+/// Set the run-time parameter until the end of the transaction, as
+/// `SET LOCAL`. The value is passed as a bind parameter.
+pub fn set_local<'a, N, V, E>(name: N, value: V) -> SetLocal<'a, E>
+where
+    N: Into<String>,
+    V: Into<String>,
+    E: From<diesel::result::Error>,
+{
+    SetLocal {
+        name: name.into(),
+        value: value.into(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `set_local`
+#[derive(Debug)]
+#[must_use]
+pub struct SetLocal<'a, E> {
+    name: String,
+    value: String,
+    _phantom: PhantomData<fn() -> (&'a (), E)>,
+}
+
+impl<'a, E> Transaction for SetLocal<'a, E>
+where
+    E: From<diesel::result::Error>,
+{
+    type Ctx = PgContext<'a>;
+    type Item = ();
+    type Err = E;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        set_config(ctx.conn(), &self.name, &self.value)?;
+        Ok(())
+    }
+}
+
+/// Defer the checks of all the deferrable constraints to the commit, as
+/// `SET CONSTRAINTS ALL DEFERRED`.
+pub fn set_constraints_deferred<'a, E>() -> SetConstraintsDeferred<'a, E>
+where
+    E: From<diesel::result::Error>,
+{
+    SetConstraintsDeferred {
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `set_constraints_deferred`
+#[derive(Debug)]
+#[must_use]
+pub struct SetConstraintsDeferred<'a, E> {
+    _phantom: PhantomData<fn() -> (&'a (), E)>,
+}
+
+impl<'a, E> Transaction for SetConstraintsDeferred<'a, E>
+where
+    E: From<diesel::result::Error>,
+{
+    type Ctx = PgContext<'a>;
+    type Item = ();
+    type Err = E;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        diesel::sql_query("SET CONSTRAINTS ALL DEFERRED").execute(ctx.conn())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,532 @@
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use diesel::QueryResult;
+use transaction::{Plan, Transaction};
+
+use DieselContext;
+
+/// Models managed by the unit of work of `DieselContext`.
+///
+/// Entities loaded by `load` are tracked in an identity map during the run,
+/// so loading the same id again returns the same instance. Changes to the
+/// instances, `persist`s and `remove`s are flushed to the database before
+/// the commit. The ids must be assigned before `persist`, e.g. UUIDs.
+///
+/// ```ignore
+/// impl Entity<PgConnection> for User {
+///     type Id = i64;
+///
+///     fn id(&self) -> i64 {
+///         self.id
+///     }
+///
+///     fn find(conn: &mut PgConnection, id: &i64) -> QueryResult<Option<Self>> {
+///         users::table.find(*id).first(conn).optional()
+///     }
+///
+///     fn insert(&self, conn: &mut PgConnection) -> QueryResult<()> {
+///         diesel::insert_into(users::table).values(self).execute(conn).map(|_| ())
+///     }
+///
+///     fn update(&self, conn: &mut PgConnection) -> QueryResult<()> {
+///         diesel::update(users::table.find(self.id)).set(self).execute(conn).map(|_| ())
+///     }
+///
+///     fn delete(id: &i64, conn: &mut PgConnection) -> QueryResult<()> {
+///         diesel::delete(users::table.find(*id)).execute(conn).map(|_| ())
+///     }
+/// }
+///
+/// load::<User, _, Error>(id).map(|user| {
+///     // updated at the commit
+///     user.unwrap().borrow_mut().name = name;
+/// })
+/// ```
+pub trait Entity<Conn>: Clone + PartialEq + 'static {
+    /// The primary key
+    type Id: Eq + Hash + Clone + 'static;
+
+    /// Entities are inserted and updated in ascending `ORDER`, and deleted in
+    /// descending `ORDER`. Give referenced tables lower ones than the tables
+    /// referencing them.
+    const ORDER: i32 = 0;
+
+    /// The primary key of the entity
+    fn id(&self) -> Self::Id;
+    /// Select the entity of the id
+    fn find(conn: &mut Conn, id: &Self::Id) -> QueryResult<Option<Self>>;
+    /// Insert the entity
+    fn insert(&self, conn: &mut Conn) -> QueryResult<()>;
+    /// Update the row of the entity
+    fn update(&self, conn: &mut Conn) -> QueryResult<()>;
+    /// Delete the row of the id
+    fn delete(id: &Self::Id, conn: &mut Conn) -> QueryResult<()>;
+}
+
+/// An entity tracked by the unit of work. Modify it through `borrow_mut`.
+pub type Managed<T> = Rc<RefCell<T>>;
+
+enum State<T> {
+    // to be inserted
+    New,
+    // in sync with the database when it was the snapshot
+    Loaded(T),
+    // to be updated
+    Dirty,
+    // to be deleted
+    Removed,
+}
+
+struct Tracked<T> {
+    entity: Managed<T>,
+    state: State<T>,
+}
+
+struct IdentityMap<K, T> {
+    entries: HashMap<K, Tracked<T>>,
+    // the order of tracking, for deterministic flushes
+    order: Vec<K>,
+    // the entities and their values at each savepoint, in the order
+    savepoints: Vec<Vec<(K, Managed<T>, T)>>,
+}
+
+impl<K, T> IdentityMap<K, T>
+where
+    K: Eq + Hash + Clone,
+    T: Clone + PartialEq,
+{
+    fn new() -> Self {
+        IdentityMap {
+            entries: HashMap::new(),
+            order: Vec::new(),
+            savepoints: Vec::new(),
+        }
+    }
+
+    // the changes are flushed, so the entities are in sync with the database
+    fn savepoint(&mut self) {
+        let entries = &self.entries;
+        let snapshot = self.order
+            .iter()
+            .map(|id| {
+                let entity = &entries[id].entity;
+                (id.clone(), entity.clone(), entity.borrow().clone())
+            })
+            .collect();
+        self.savepoints.push(snapshot);
+    }
+
+    fn release(&mut self) {
+        self.savepoints.pop();
+    }
+
+    // keep the instances loaded before the savepoint tracked, with their
+    // values at the savepoint
+    fn rollback_to_savepoint(&mut self) {
+        let snapshot = match self.savepoints.pop() {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        self.entries.clear();
+        self.order.clear();
+        for (id, entity, value) in snapshot {
+            *entity.borrow_mut() = value.clone();
+            self.entries.insert(
+                id.clone(),
+                Tracked {
+                    entity: entity,
+                    state: State::Loaded(value),
+                },
+            );
+            self.order.push(id);
+        }
+    }
+
+    fn track(&mut self, id: K, entity: T, state: State<T>) -> Managed<T> {
+        let entity = Rc::new(RefCell::new(entity));
+        if self.entries
+            .insert(
+                id.clone(),
+                Tracked {
+                    entity: entity.clone(),
+                    state: state,
+                },
+            )
+            .is_none()
+        {
+            self.order.push(id);
+        }
+        entity
+    }
+
+    fn untrack(&mut self, id: &K) {
+        self.entries.remove(id);
+        self.order.retain(|k| k != id);
+    }
+
+    fn save<Conn>(&mut self, conn: &mut Conn) -> QueryResult<()>
+    where
+        T: Entity<Conn, Id = K>,
+    {
+        for id in &self.order {
+            let tracked = self.entries.get_mut(id).expect("ordered ids are tracked");
+            let entity = tracked.entity.borrow().clone();
+            match tracked.state {
+                State::New => entity.insert(conn)?,
+                State::Dirty => entity.update(conn)?,
+                State::Loaded(ref snapshot) if *snapshot != entity => entity.update(conn)?,
+                State::Loaded(_) | State::Removed => continue,
+            }
+            tracked.state = State::Loaded(entity);
+        }
+        Ok(())
+    }
+
+    fn delete<Conn>(&mut self, conn: &mut Conn) -> QueryResult<()>
+    where
+        T: Entity<Conn, Id = K>,
+    {
+        let removed: Vec<K> = self.order
+            .iter()
+            .filter(|id| matches!(self.entries[*id].state, State::Removed))
+            .cloned()
+            .collect();
+        for id in removed {
+            T::delete(&id, conn)?;
+            self.untrack(&id);
+        }
+        Ok(())
+    }
+}
+
+type FlushFn<Conn> = fn(&mut dyn Any, &mut Conn) -> QueryResult<()>;
+
+type SavepointFn = fn(&mut dyn Any);
+
+struct Slot<Conn> {
+    type_id: TypeId,
+    order: i32,
+    // `IdentityMap<T::Id, T>`
+    map: Box<dyn Any>,
+    save: FlushFn<Conn>,
+    delete: FlushFn<Conn>,
+    savepoint: SavepointFn,
+    release: SavepointFn,
+    rollback_to_savepoint: SavepointFn,
+}
+
+fn identities_mut<K: 'static, T: 'static>(map: &mut dyn Any) -> &mut IdentityMap<K, T> {
+    map.downcast_mut()
+        .expect("the map of T is IdentityMap<T::Id, T>")
+}
+
+fn save<Conn, T: Entity<Conn>>(map: &mut dyn Any, conn: &mut Conn) -> QueryResult<()> {
+    identities_mut::<T::Id, T>(map).save(conn)
+}
+
+fn delete<Conn, T: Entity<Conn>>(map: &mut dyn Any, conn: &mut Conn) -> QueryResult<()> {
+    identities_mut::<T::Id, T>(map).delete(conn)
+}
+
+fn savepoint<Conn, T: Entity<Conn>>(map: &mut dyn Any) {
+    identities_mut::<T::Id, T>(map).savepoint()
+}
+
+fn release<Conn, T: Entity<Conn>>(map: &mut dyn Any) {
+    identities_mut::<T::Id, T>(map).release()
+}
+
+fn rollback_to_savepoint<Conn, T: Entity<Conn>>(map: &mut dyn Any) {
+    identities_mut::<T::Id, T>(map).rollback_to_savepoint()
+}
+
+/// The identity maps of a run, held by `DieselContext`
+pub(crate) struct UnitOfWork<Conn> {
+    // in the order of registration
+    slots: Vec<Slot<Conn>>,
+    // the number of slots at each savepoint
+    savepoints: Vec<usize>,
+}
+
+impl<Conn> UnitOfWork<Conn> {
+    pub(crate) fn new() -> Self {
+        UnitOfWork {
+            slots: Vec::new(),
+            savepoints: Vec::new(),
+        }
+    }
+
+    /// Forget the entities and their pending changes
+    pub(crate) fn clear(&mut self) {
+        self.slots.clear();
+        self.savepoints.clear();
+    }
+
+    /// Remember the tracked entities. Call it after `flush`.
+    pub(crate) fn savepoint(&mut self) {
+        for slot in &mut self.slots {
+            (slot.savepoint)(&mut *slot.map);
+        }
+        self.savepoints.push(self.slots.len());
+    }
+
+    /// Keep the changes made since the last savepoint
+    pub(crate) fn release(&mut self) {
+        if let Some(len) = self.savepoints.pop() {
+            for slot in &mut self.slots[..len] {
+                (slot.release)(&mut *slot.map);
+            }
+        }
+    }
+
+    /// Forget the entities tracked since the last savepoint and restore the
+    /// others to their values at the savepoint
+    pub(crate) fn rollback_to_savepoint(&mut self) {
+        if let Some(len) = self.savepoints.pop() {
+            self.slots.truncate(len);
+            for slot in &mut self.slots {
+                (slot.rollback_to_savepoint)(&mut *slot.map);
+            }
+        }
+    }
+
+    /// Write the pending changes to the database
+    pub(crate) fn flush(&mut self, conn: &mut Conn) -> QueryResult<()> {
+        let mut slots: Vec<&mut Slot<Conn>> = self.slots.iter_mut().collect();
+        slots.sort_by_key(|slot| slot.order);
+        for slot in slots.iter_mut() {
+            (slot.save)(&mut *slot.map, conn)?;
+        }
+        for slot in slots.iter_mut().rev() {
+            (slot.delete)(&mut *slot.map, conn)?;
+        }
+        Ok(())
+    }
+
+    fn identities<T: Entity<Conn>>(&mut self) -> &mut IdentityMap<T::Id, T> {
+        let type_id = TypeId::of::<T>();
+        let pos = match self.slots.iter().position(|slot| slot.type_id == type_id) {
+            Some(pos) => pos,
+            None => {
+                self.slots.push(Slot {
+                    type_id: type_id,
+                    order: T::ORDER,
+                    map: Box::new(IdentityMap::<T::Id, T>::new()),
+                    save: save::<Conn, T>,
+                    delete: delete::<Conn, T>,
+                    savepoint: savepoint::<Conn, T>,
+                    release: release::<Conn, T>,
+                    rollback_to_savepoint: rollback_to_savepoint::<Conn, T>,
+                });
+                self.slots.len() - 1
+            }
+        };
+        self.slots[pos]
+            .map
+            .downcast_mut()
+            .expect("the map of T is IdentityMap<T::Id, T>")
+    }
+}
+
+/// Load the entity of the id. Returns the tracked instance if it is already
+/// loaded or persisted in the run, and `None` if it is removed.
+pub fn load<'a, T, Conn, E>(id: T::Id) -> Load<'a, T, Conn, E>
+where
+    T: Entity<Conn>,
+{
+    Load {
+        id: id,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `load`
+#[derive(Debug)]
+#[must_use]
+pub struct Load<'a, T: Entity<Conn>, Conn: 'a, E> {
+    id: T::Id,
+    _phantom: PhantomData<fn() -> (&'a Conn, E)>,
+}
+
+impl<'a, T, Conn, E> Transaction for Load<'a, T, Conn, E>
+where
+    T: Entity<Conn>,
+    E: From<diesel::result::Error>,
+{
+    type Ctx = DieselContext<'a, Conn>;
+    type Item = Option<Managed<T>>;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.requires_write();
+        if let Some(tracked) = ctx.unit_of_work.identities::<T>().entries.get(&self.id) {
+            return Ok(match tracked.state {
+                State::Removed => None,
+                _ => Some(tracked.entity.clone()),
+            });
+        }
+        let found = T::find(ctx.conn(), &self.id)?;
+        Ok(found.map(|entity| {
+            let snapshot = entity.clone();
+            ctx.unit_of_work
+                .identities::<T>()
+                .track(self.id.clone(), entity, State::Loaded(snapshot))
+        }))
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("load")
+    }
+}
+
+/// Track a new entity to insert it. If an entity of the same id is already
+/// tracked, its value is replaced.
+pub fn persist<'a, T, Conn, E>(entity: T) -> Persist<'a, T, Conn, E>
+where
+    T: Entity<Conn>,
+{
+    Persist {
+        entity: entity,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `persist`
+#[derive(Debug)]
+#[must_use]
+pub struct Persist<'a, T, Conn: 'a, E> {
+    entity: T,
+    _phantom: PhantomData<fn() -> (&'a Conn, E)>,
+}
+
+impl<'a, T, Conn, E> Transaction for Persist<'a, T, Conn, E>
+where
+    T: Entity<Conn>,
+{
+    type Ctx = DieselContext<'a, Conn>;
+    type Item = Managed<T>;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.requires_write();
+        let id = self.entity.id();
+        let identities = ctx.unit_of_work.identities::<T>();
+        if let Some(tracked) = identities.entries.get_mut(&id) {
+            *tracked.entity.borrow_mut() = self.entity.clone();
+            if let State::Removed = tracked.state {
+                // the row is still there
+                tracked.state = State::Dirty;
+            }
+            return Ok(tracked.entity.clone());
+        }
+        Ok(identities.track(id, self.entity.clone(), State::New))
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("persist")
+    }
+}
+
+/// Delete the entity of the id. Later `load`s of the id return `None`.
+pub fn remove<'a, T, Conn, E>(id: T::Id) -> Remove<'a, T, Conn, E>
+where
+    T: Entity<Conn>,
+{
+    Remove {
+        id: id,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `remove`
+#[derive(Debug)]
+#[must_use]
+pub struct Remove<'a, T: Entity<Conn>, Conn: 'a, E> {
+    id: T::Id,
+    _phantom: PhantomData<fn() -> (&'a Conn, E)>,
+}
+
+impl<'a, T, Conn, E> Transaction for Remove<'a, T, Conn, E>
+where
+    T: Entity<Conn>,
+    E: From<diesel::result::Error>,
+{
+    type Ctx = DieselContext<'a, Conn>;
+    type Item = ();
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.requires_write();
+        if !ctx.unit_of_work.identities::<T>().entries.contains_key(&self.id) {
+            // track it to delete at the flush
+            match T::find(ctx.conn(), &self.id)? {
+                Some(entity) => {
+                    let snapshot = entity.clone();
+                    ctx.unit_of_work
+                        .identities::<T>()
+                        .track(self.id.clone(), entity, State::Loaded(snapshot));
+                }
+                None => return Ok(()),
+            }
+        }
+        let identities = ctx.unit_of_work.identities::<T>();
+        let inserted = match identities.entries.get_mut(&self.id) {
+            Some(tracked) => match tracked.state {
+                State::New => true,
+                _ => {
+                    tracked.state = State::Removed;
+                    false
+                }
+            },
+            None => false,
+        };
+        if inserted {
+            // never written
+            identities.untrack(&self.id);
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("remove")
+    }
+}
+
+/// Write the pending changes of the unit of work now, e.g. before running
+/// queries by `with_conn` that depend on them.
+pub fn flush<'a, Conn, E>() -> Flush<'a, Conn, E> {
+    Flush {
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `flush`
+#[derive(Debug)]
+#[must_use]
+pub struct Flush<'a, Conn: 'a, E> {
+    _phantom: PhantomData<fn() -> (&'a Conn, E)>,
+}
+
+impl<'a, Conn, E> Transaction for Flush<'a, Conn, E>
+where
+    E: From<diesel::result::Error>,
+{
+    type Ctx = DieselContext<'a, Conn>;
+    type Item = ();
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.requires_write();
+        ctx.flush_unit_of_work()?;
+        Ok(())
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("flush")
+    }
+}
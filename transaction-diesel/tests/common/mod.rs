@@ -0,0 +1,58 @@
+#![allow(dead_code)]
+
+use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+use diesel::sqlite::SqliteConnection;
+use transaction::PropagationError;
+
+#[derive(Debug)]
+pub enum Error {
+    Diesel(diesel::result::Error),
+    Propagation(PropagationError),
+    #[cfg(feature = "r2d2")]
+    Pool(diesel::r2d2::PoolError),
+    Fail,
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Self {
+        Error::Diesel(e)
+    }
+}
+
+impl From<PropagationError> for Error {
+    fn from(e: PropagationError) -> Self {
+        Error::Propagation(e)
+    }
+}
+
+#[cfg(feature = "r2d2")]
+impl From<diesel::r2d2::PoolError> for Error {
+    fn from(e: diesel::r2d2::PoolError) -> Self {
+        Error::Pool(e)
+    }
+}
+
+/// an in-memory database with `users (id, name)`
+pub fn connection() -> SqliteConnection {
+    let mut cn = SqliteConnection::establish(":memory:").unwrap();
+    execute(&mut cn, "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)");
+    cn
+}
+
+pub fn execute(cn: &mut SqliteConnection, sql: &str) {
+    diesel::sql_query(sql).execute(cn).unwrap();
+}
+
+#[derive(QueryableByName)]
+struct Count {
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+}
+
+pub fn count(cn: &mut SqliteConnection, table: &str) -> i64 {
+    diesel::sql_query(format!("SELECT COUNT(*) AS count FROM {}", table))
+        .get_result::<Count>(cn)
+        .unwrap()
+        .count
+}
@@ -0,0 +1,87 @@
+#![cfg(feature = "derive")]
+
+extern crate diesel;
+extern crate transaction;
+extern crate transaction_diesel;
+
+mod common;
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use transaction::prelude::*;
+use transaction_diesel::{run, ReadOnly, TxRepository};
+
+use common::{connection, count, execute};
+
+table! {
+    users (id) {
+        id -> Integer,
+        name -> Text,
+    }
+}
+
+#[derive(Debug, PartialEq, Queryable, AsChangeset, TxRepository)]
+#[diesel(table_name = users)]
+#[tx_repository(conn = SqliteConnection)]
+struct User {
+    id: i32,
+    name: String,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = users)]
+struct NewUser {
+    name: String,
+}
+
+fn assert_read_only<Tx: ReadOnly>(tx: Tx) -> Tx {
+    tx
+}
+
+#[test]
+fn queries_are_read_only() {
+    let mut cn = connection();
+    execute(&mut cn, "INSERT INTO users VALUES (1, 'user1'), (2, 'user2')");
+    let tx = assert_read_only(User::find(1))
+        .join(assert_read_only(User::find_many(vec![1, 2])))
+        .join(assert_read_only(User::exists(3)))
+        .join(assert_read_only(User::count()));
+    let (((user, users), exists), count) = run::<_, _, diesel::result::Error, _>(&mut cn, tx).unwrap();
+    assert_eq!(user.map(|u| u.name), Some("user1".to_string()));
+    assert_eq!(users.len(), 2);
+    assert!(!exists);
+    assert_eq!(count, 2);
+}
+
+#[test]
+fn create_returns_the_inserted_row() {
+    let mut cn = connection();
+    let tx = User::create(NewUser { name: "user1".to_string() });
+    let user = run::<_, _, diesel::result::Error, _>(&mut cn, tx).unwrap();
+    assert_eq!(user, User { id: 1, name: "user1".to_string() });
+    assert_eq!(count(&mut cn, "users"), 1);
+}
+
+#[test]
+fn update_returns_the_updated_row() {
+    let mut cn = connection();
+    execute(&mut cn, "INSERT INTO users VALUES (1, 'user1')");
+    let tx = User { id: 1, name: "renamed".to_string() }
+        .update()
+        .join(User { id: 2, name: "missing".to_string() }.update())
+        .join(User::find(1));
+    let ((updated, missing), found) = run::<_, _, diesel::result::Error, _>(&mut cn, tx).unwrap();
+    assert_eq!(updated, Some(User { id: 1, name: "renamed".to_string() }));
+    assert_eq!(missing, None);
+    assert_eq!(found, updated);
+}
+
+#[test]
+fn delete_returns_whether_the_row_existed() {
+    let mut cn = connection();
+    execute(&mut cn, "INSERT INTO users VALUES (1, 'user1')");
+    let tx = User::delete(1).join(User::delete(1));
+    let deleted = run::<_, _, diesel::result::Error, _>(&mut cn, tx).unwrap();
+    assert_eq!(deleted, (true, false));
+    assert_eq!(count(&mut cn, "users"), 0);
+}
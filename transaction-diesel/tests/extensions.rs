@@ -0,0 +1,106 @@
+extern crate diesel;
+extern crate transaction;
+extern crate transaction_diesel;
+
+mod common;
+
+use diesel::sqlite::SqliteConnection;
+use transaction::prelude::*;
+use transaction_diesel::{run_with_extensions, with_conn_ext, with_conn_ext_ro, DieselContext, Extensions, ReadOnly};
+
+use common::{connection, count, execute, Error};
+
+#[derive(Debug, Clone, PartialEq)]
+struct CurrentUser(i32);
+
+fn current_user<'a>() -> impl TxFor<DieselContext<'a, SqliteConnection>, Option<CurrentUser>, Error> {
+    with_conn_ext(|_: &mut SqliteConnection, ext: &mut Extensions| Ok(ext.get::<CurrentUser>().cloned()))
+}
+
+#[test]
+fn extensions_hold_a_value_for_each_type() {
+    let mut ext = Extensions::new();
+    assert!(!ext.contains::<CurrentUser>());
+    assert_eq!(ext.insert(CurrentUser(1)), None);
+    assert_eq!(ext.insert(CurrentUser(2)), Some(CurrentUser(1)));
+    ext.insert("request");
+    ext.get_mut::<CurrentUser>().unwrap().0 += 1;
+    assert_eq!(ext.get::<CurrentUser>(), Some(&CurrentUser(3)));
+    assert_eq!(ext.get::<&str>(), Some(&"request"));
+    assert_eq!(ext.remove::<CurrentUser>(), Some(CurrentUser(3)));
+    assert!(!ext.contains::<CurrentUser>());
+    ext.clear();
+    assert!(!ext.contains::<&str>());
+}
+
+#[test]
+fn run_with_extensions_passes_the_values() {
+    let mut cn = connection();
+    let mut ext = Extensions::new();
+    ext.insert(CurrentUser(1));
+    let tx = with_conn_ext(|cn: &mut SqliteConnection, ext: &mut Extensions| {
+        let CurrentUser(id) = *ext.get::<CurrentUser>().unwrap();
+        execute(cn, &format!("INSERT INTO users VALUES ({}, 'user{}')", id, id));
+        Ok::<_, Error>(())
+    });
+    run_with_extensions(&mut cn, ext, tx).unwrap();
+    assert_eq!(count(&mut cn, "users"), 1);
+}
+
+fn assert_read_only<Tx: ReadOnly>(tx: Tx) -> Tx {
+    tx
+}
+
+#[test]
+fn with_conn_ext_ro_is_read_only() {
+    let mut cn = connection();
+    execute(&mut cn, "INSERT INTO users VALUES (1, 'user1')");
+    let mut ext = Extensions::new();
+    ext.insert(CurrentUser(1));
+    let tx = assert_read_only(with_conn_ext_ro(|cn: &mut SqliteConnection, ext: &mut Extensions| {
+        let CurrentUser(id) = *ext.get::<CurrentUser>().unwrap();
+        Ok::<_, Error>((id, count(cn, "users")))
+    }));
+    assert_eq!(run_with_extensions(&mut cn, ext, tx).unwrap(), (1, 1));
+}
+
+#[test]
+fn changes_are_seen_by_later_steps() {
+    let mut cn = connection();
+    let tx = current_user()
+        .and_then(|user| {
+            assert_eq!(user, None);
+            with_ctx(|ctx: &mut DieselContext<SqliteConnection>| {
+                ctx.extensions().insert(CurrentUser(1));
+                Ok(())
+            })
+        })
+        .and_then(|_| current_user());
+    let user = run_with_extensions(&mut cn, Extensions::new(), tx).unwrap();
+    assert_eq!(user, Some(CurrentUser(1)));
+}
+
+#[cfg(feature = "r2d2")]
+#[test]
+fn requires_new_gets_a_copy_of_the_extensions() {
+    use diesel::r2d2::{ConnectionManager, Pool};
+    use transaction_diesel::run_pooled_with_extensions;
+
+    let pool = Pool::builder()
+        .max_size(2)
+        .build(ConnectionManager::<SqliteConnection>::new(":memory:"))
+        .unwrap();
+    let mut ext = Extensions::new();
+    ext.insert(CurrentUser(1));
+    let inner = with_conn_ext(|_: &mut SqliteConnection, ext: &mut Extensions| {
+        let user = ext.insert(CurrentUser(2));
+        Ok::<_, Error>(user)
+    });
+    let tx = inner
+        .requires_new()
+        .and_then(|user| current_user().map(move |outer| (user.clone(), outer)));
+    let (inner, outer) = run_pooled_with_extensions(&pool, ext, tx).unwrap();
+    assert_eq!(inner, Some(CurrentUser(1)));
+    // the change in the new transaction is not seen by the outer one
+    assert_eq!(outer, Some(CurrentUser(1)));
+}
@@ -0,0 +1,101 @@
+#![cfg(feature = "postgres")]
+
+extern crate diesel;
+extern crate transaction;
+#[macro_use]
+extern crate transaction_diesel;
+
+use std::cell::RefCell;
+use std::env;
+
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Bool};
+use transaction::prelude::*;
+use transaction_diesel::{advisory_xact_lock, run, try_advisory_xact_lock, LockNotAcquired};
+
+table! {
+    lock_users (id) {
+        id -> Integer,
+        name -> Text,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable)]
+struct User {
+    id: i32,
+    name: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum Error {
+    Diesel(diesel::result::Error),
+    Lock(LockNotAcquired),
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Self {
+        Error::Diesel(e)
+    }
+}
+
+impl From<LockNotAcquired> for Error {
+    fn from(e: LockNotAcquired) -> Self {
+        Error::Lock(e)
+    }
+}
+
+fn connection() -> PgConnection {
+    let url = env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+    PgConnection::establish(&url).unwrap()
+}
+
+// takes the lock in another session
+fn try_lock_elsewhere(cn: &mut PgConnection, key: i64) -> bool {
+    diesel::select(sql::<Bool>("pg_try_advisory_xact_lock(").bind::<BigInt, _>(key).sql(")"))
+        .get_result(cn)
+        .unwrap()
+}
+
+#[test]
+#[ignore = "requires a Postgres server at DATABASE_URL"]
+fn lock_row_for_update_returns_the_row() {
+    let mut cn = connection();
+    cn.batch_execute(
+        "CREATE TEMPORARY TABLE lock_users (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+         INSERT INTO lock_users VALUES (1, 'user1');",
+    )
+    .unwrap();
+    let tx = lock_row_for_update!(lock_users::table.find(1))
+        .join(lock_row_for_update!(lock_users::table.find(2)));
+    let (found, missing): (Option<User>, Option<User>) = run::<_, _, Error, _>(&mut cn, tx).unwrap();
+    assert_eq!(found, Some(User { id: 1, name: "user1".to_string() }));
+    assert_eq!(missing, None);
+}
+
+#[test]
+#[ignore = "requires a Postgres server at DATABASE_URL"]
+fn advisory_lock_is_held_until_the_end_of_the_transaction() {
+    let mut cn = connection();
+    let other = RefCell::new(connection());
+    let tx = advisory_xact_lock::<Error>(34_001)
+        .and_then(|_| try_advisory_xact_lock(34_001))
+        .map(|_| other.borrow_mut().transaction(|cn| Ok::<_, Error>(try_lock_elsewhere(cn, 34_001))));
+    // not acquired by another session while the transaction runs
+    assert!(!run(&mut cn, tx).unwrap().unwrap());
+    let acquired = other.borrow_mut().transaction(|cn| Ok::<_, Error>(try_lock_elsewhere(cn, 34_001)));
+    assert_eq!(acquired, Ok(true));
+}
+
+#[test]
+#[ignore = "requires a Postgres server at DATABASE_URL"]
+fn try_advisory_lock_fails_if_the_lock_is_held() {
+    let mut cn = connection();
+    let mut other = connection();
+    other.begin_test_transaction().unwrap();
+    assert!(try_lock_elsewhere(&mut other, 34_002));
+    let ret = run(&mut cn, try_advisory_xact_lock::<Error>(34_002));
+    assert_eq!(ret, Err(Error::Lock(LockNotAcquired { key: 34_002 })));
+}
@@ -0,0 +1,135 @@
+#![cfg(all(feature = "postgres", feature = "r2d2"))]
+
+extern crate diesel;
+extern crate transaction;
+extern crate transaction_diesel;
+
+use std::cell::Cell;
+use std::env;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+use diesel::pg::PgConnection;
+use diesel::r2d2::{ConnectionManager, Pool, PoolError};
+use diesel::Connection;
+use transaction::prelude::*;
+use transaction_diesel::{notify, run_pooled, Listener};
+
+#[allow(dead_code)]
+#[derive(Debug)]
+enum Error {
+    Diesel(diesel::result::Error),
+    Pool(PoolError),
+    Fail,
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Self {
+        Error::Diesel(e)
+    }
+}
+
+impl From<PoolError> for Error {
+    fn from(e: PoolError) -> Self {
+        Error::Pool(e)
+    }
+}
+
+fn database_url() -> String {
+    env::var("DATABASE_URL").expect("DATABASE_URL is not set")
+}
+
+fn listener() -> (Listener<Error>, Pool<ConnectionManager<PgConnection>>) {
+    let url = database_url();
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(ConnectionManager::<PgConnection>::new(url.as_str()))
+        .unwrap();
+    let listener = Listener::new(PgConnection::establish(&url).unwrap(), pool.clone());
+    (listener, pool)
+}
+
+// poll until a notification is dispatched or a handler fails
+fn poll(listener: &mut Listener<Error>) -> Result<usize, Error> {
+    for _ in 0..50 {
+        match listener.poll() {
+            Ok(0) => thread::sleep(Duration::from_millis(20)),
+            r => return r,
+        }
+    }
+    Ok(0)
+}
+
+#[test]
+#[ignore = "requires a Postgres server at DATABASE_URL"]
+fn notifications_are_kept_until_the_handlers_succeed() {
+    let (mut listener, pool) = listener();
+
+    let (first, second) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+    let calls = first.clone();
+    listener
+        .on("transaction_diesel_test", move |_| {
+            calls.set(calls.get() + 1);
+            ok(())
+        })
+        .unwrap();
+    let calls = second.clone();
+    listener
+        .on("transaction_diesel_test", move |_| {
+            calls.set(calls.get() + 1);
+            // fails at the first time
+            let failed = calls.get() == 1;
+            lazy(move || if failed { Err(Error::Fail) } else { Ok(()) })
+        })
+        .unwrap();
+
+    run_pooled(&pool, notify::<_, _, Error>("transaction_diesel_test", "1")).unwrap();
+    match poll(&mut listener) {
+        Err(Error::Fail) => (),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    // dispatched again from the failed handler
+    assert_eq!(listener.poll().unwrap(), 1);
+    assert_eq!((first.get(), second.get()), (1, 2));
+}
+
+#[test]
+#[ignore = "requires a Postgres server at DATABASE_URL"]
+fn rolled_back_notifications_are_not_sent() {
+    let (mut listener, pool) = listener();
+    listener.on("transaction_diesel_rollback", |_| ok(())).unwrap();
+
+    let tx = notify::<_, _, Error>("transaction_diesel_rollback", "1")
+        .and_then(|_| lazy(|| Err::<(), _>(Error::Fail)));
+    assert!(run_pooled(&pool, tx).is_err());
+    assert_eq!(poll(&mut listener).unwrap(), 0);
+}
+
+#[test]
+#[ignore = "requires a Postgres server at DATABASE_URL"]
+fn failing_notifications_are_given_up_by_dead_letter() {
+    let (mut listener, pool) = listener();
+    let calls = Rc::new(Cell::new(0));
+    let c = calls.clone();
+    listener
+        .on("transaction_diesel_dead_letter", move |_| {
+            c.set(c.get() + 1);
+            lazy(|| Err::<(), _>(Error::Fail))
+        })
+        .unwrap();
+    let given_up = Rc::new(Cell::new(None));
+    let g = given_up.clone();
+    listener.dead_letter(1, move |n, _| g.set(Some(n.payload.clone())));
+
+    run_pooled(&pool, notify::<_, _, Error>("transaction_diesel_dead_letter", "1")).unwrap();
+    match poll(&mut listener) {
+        Err(Error::Fail) => (),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    // given up after a retry
+    assert_eq!(listener.poll().unwrap(), 1);
+    assert_eq!(calls.get(), 2);
+    assert_eq!(given_up.take(), Some("1".to_string()));
+    assert_eq!(listener.poll().unwrap(), 0);
+}
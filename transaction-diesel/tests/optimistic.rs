@@ -0,0 +1,123 @@
+extern crate diesel;
+extern crate transaction;
+extern crate transaction_diesel;
+
+mod common;
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use transaction::prelude::*;
+use transaction::PropagationError;
+use transaction_diesel::{run, update_if_version, with_conn_ro, Conflict, DieselContext, Versioned};
+
+use common::execute;
+
+table! {
+    items (id) {
+        id -> Integer,
+        name -> Text,
+        version -> Integer,
+    }
+}
+
+impl Versioned for items::table {
+    type Version = items::version;
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+enum Error {
+    Diesel(diesel::result::Error),
+    Propagation(PropagationError),
+    Conflict,
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Self {
+        Error::Diesel(e)
+    }
+}
+
+impl From<PropagationError> for Error {
+    fn from(e: PropagationError) -> Self {
+        Error::Propagation(e)
+    }
+}
+
+impl From<Conflict> for Error {
+    fn from(_: Conflict) -> Self {
+        Error::Conflict
+    }
+}
+
+// an item of id 1 at version 1
+fn connection() -> SqliteConnection {
+    let mut cn = SqliteConnection::establish(":memory:").unwrap();
+    execute(
+        &mut cn,
+        "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL, version INTEGER NOT NULL)",
+    );
+    execute(&mut cn, "INSERT INTO items VALUES (1, 'old', 1)");
+    cn
+}
+
+fn item(cn: &mut SqliteConnection) -> (String, i32) {
+    items::table
+        .find(1)
+        .select((items::name, items::version))
+        .first(cn)
+        .unwrap()
+}
+
+fn rename<'a>(
+    id: i32,
+    version: i32,
+    name: &'static str,
+) -> impl TxFor<DieselContext<'a, SqliteConnection>, (), Error> {
+    update_if_version(items::table, id, version, items::name.eq(name))
+}
+
+#[test]
+fn update_increments_version() {
+    let mut cn = connection();
+    assert!(run(&mut cn, rename(1, 1, "new")).is_ok());
+    assert_eq!(item(&mut cn), ("new".to_string(), 2));
+}
+
+#[test]
+fn update_of_other_version_conflicts() {
+    let mut cn = connection();
+    match run(&mut cn, rename(1, 0, "new")) {
+        Err(Error::Conflict) => (),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    assert_eq!(item(&mut cn), ("old".to_string(), 1));
+}
+
+#[test]
+fn update_of_missing_row_is_not_found() {
+    let mut cn = connection();
+    match run(&mut cn, rename(2, 1, "new")) {
+        Err(Error::Diesel(diesel::result::Error::NotFound)) => (),
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn conflicts_are_retried() {
+    let mut cn = connection();
+    // the first attempt uses a stale version
+    let tx = retry_if(
+        2,
+        |e: &Error| matches!(*e, Error::Conflict),
+        |i| {
+            with_conn_ro(move |cn: &mut SqliteConnection| {
+                let version = items::table.find(1).select(items::version).first::<i32>(cn)?;
+                Ok(if i == 0 { version - 1 } else { version })
+            }).and_then(|version| rename(1, version, "new"))
+                .nested()
+        },
+    ).map_err(|mut errors: Vec<Error>| errors.pop().unwrap());
+    assert!(run(&mut cn, tx).is_ok());
+    assert_eq!(item(&mut cn), ("new".to_string(), 2));
+}
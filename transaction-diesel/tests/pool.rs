@@ -0,0 +1,250 @@
+#![cfg(all(feature = "r2d2", feature = "sqlite"))]
+
+extern crate diesel;
+extern crate transaction;
+extern crate transaction_diesel;
+
+mod common;
+
+use std::cell::Cell;
+use std::env;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use diesel::connection::{AnsiTransactionManager, Connection, SimpleConnection, TransactionManager};
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, Pool};
+use diesel::RunQueryDsl;
+use diesel::sqlite::SqliteConnection;
+use transaction::prelude::*;
+use transaction::{cached, PropagationError, SharedCache};
+use transaction_diesel::{
+    run, run_pooled, run_pooled_with_retry, run_read_only, run_routed, with_conn, with_conn_ro, DieselContext,
+};
+
+use common::{count, execute, Error};
+
+type SqlitePool = Pool<ConnectionManager<SqliteConnection>>;
+
+// a database file with `users`
+fn database(name: &str) -> String {
+    let path = env::temp_dir().join(format!("transaction-diesel-{}-{}.db", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    let path = path.to_str().unwrap().to_string();
+    execute(
+        &mut SqliteConnection::establish(&path).unwrap(),
+        "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)",
+    );
+    path
+}
+
+// a database file shared by the primary and the replica pools
+fn pools(name: &str) -> (SqlitePool, SqlitePool) {
+    let path = database(name);
+    let pool = || {
+        Pool::builder()
+            .max_size(1)
+            .build(ConnectionManager::new(path.as_str()))
+            .unwrap()
+    };
+    (pool(), pool())
+}
+
+// breaks the transaction manager of the first connection of the pool
+#[derive(Debug, Default)]
+struct BreakFirst(AtomicBool);
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for BreakFirst {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        if !self.0.swap(true, Ordering::SeqCst) {
+            AnsiTransactionManager::transaction_manager_status_mut(conn).set_in_error();
+        }
+        Ok(())
+    }
+}
+
+// a transaction not known to diesel makes `BEGIN` fail
+#[derive(Debug)]
+struct OpenTransaction;
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for OpenTransaction {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.batch_execute("BEGIN").map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+fn broken_pool() -> SqlitePool {
+    Pool::builder()
+        .max_size(1)
+        .test_on_check_out(false)
+        .connection_customizer(Box::new(BreakFirst::default()))
+        .build(ConnectionManager::new(":memory:"))
+        .unwrap()
+}
+
+fn insert_sql(id: i32) -> String {
+    format!("INSERT INTO users VALUES ({}, 'user{}')", id, id)
+}
+
+fn try_insert<'a>(id: i32) -> impl TxFor<DieselContext<'a, SqliteConnection>, (), Error> {
+    with_conn(move |cn: &mut SqliteConnection| {
+        diesel::sql_query(insert_sql(id)).execute(cn)?;
+        Ok(())
+    })
+}
+
+#[test]
+fn routed_writes_run_on_primary() {
+    let (primary, replica) = pools("routed-writes");
+    let ret = run_routed(&primary, &replica, try_insert(1));
+    assert!(ret.is_ok());
+    assert_eq!(count(&mut primary.get().unwrap(), "users"), 1);
+}
+
+#[test]
+fn replica_transaction_is_read_only() {
+    let (primary, replica) = pools("replica-read-only");
+    // a write hidden in `with_conn_ro` is not rerun on the primary
+    let tx = with_conn_ro(|cn: &mut SqliteConnection| {
+        diesel::sql_query(insert_sql(1)).execute(cn)?;
+        Ok(())
+    });
+    match run_routed::<_, _, Error, _>(&primary, &replica, tx) {
+        Err(Error::Diesel(_)) => (),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    assert_eq!(count(&mut primary.get().unwrap(), "users"), 0);
+    // the replica connection is writable again outside of the run
+    execute(&mut replica.get().unwrap(), &insert_sql(2));
+    assert_eq!(count(&mut primary.get().unwrap(), "users"), 1);
+}
+
+#[test]
+fn routed_writes_are_not_called_on_the_replica() {
+    let (primary, replica) = pools("routed-not-called");
+    let calls = Cell::new(0);
+    let tx = with_conn(|cn: &mut SqliteConnection| {
+        calls.set(calls.get() + 1);
+        diesel::sql_query(insert_sql(1)).execute(cn)?;
+        Ok::<_, Error>(())
+    });
+    run_routed(&primary, &replica, tx).unwrap();
+    assert_eq!(calls.get(), 1);
+    assert_eq!(count(&mut primary.get().unwrap(), "users"), 1);
+}
+
+#[test]
+fn replica_is_writable_after_begin_fails() {
+    let (primary, _) = pools("replica-begin-fails");
+    let replica = Pool::builder()
+        .max_size(1)
+        .connection_customizer(Box::new(OpenTransaction))
+        .build(ConnectionManager::new(":memory:"))
+        .unwrap();
+    let tx = with_conn_ro(|_: &mut SqliteConnection| Ok::<_, Error>(()));
+    match run_routed(&primary, &replica, tx) {
+        Err(Error::Diesel(_)) => (),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    execute(&mut replica.get().unwrap(), "CREATE TABLE t (id INTEGER)");
+}
+
+#[test]
+fn routed_never_runs_outside_of_transactions() {
+    let (primary, replica) = pools("routed-never");
+    let ret = run_routed(&primary, &replica, try_insert(1).never());
+    assert!(ret.is_ok());
+    assert_eq!(count(&mut primary.get().unwrap(), "users"), 1);
+}
+
+#[test]
+fn routed_nested_rolls_back_the_savepoint() {
+    let (primary, replica) = pools("routed-nested");
+    let tx = try_insert(1).and_then(|_| {
+        try_insert(2)
+            .and_then(|_| lazy(|| Err(Error::Fail)))
+            .nested()
+            .recover::<Error, _>(|_| ())
+    });
+    let ret = run_routed(&primary, &replica, tx.nested());
+    assert!(ret.is_ok());
+    assert_eq!(count(&mut primary.get().unwrap(), "users"), 1);
+}
+
+#[test]
+fn memo_named_and_cached_are_read_only() {
+    let (primary, replica) = pools("read-only-wrappers");
+    execute(&mut primary.get().unwrap(), &insert_sql(1));
+    let cache = SharedCache::new();
+    let count_users = || {
+        with_conn_ro(|cn: &mut SqliteConnection| Ok::<_, Error>(count(cn, "users")))
+    };
+    let tx = count_users()
+        .memo()
+        .join(count_users().memo_by("users"))
+        .join(cached(&cache, "users", count_users()).named("cached"));
+    assert_eq!(run_read_only(&replica, tx).unwrap(), ((1, 1), 1));
+    assert_eq!(cache.get(&"users"), Some(1));
+}
+
+fn select_one<'a>() -> impl TxFor<DieselContext<'a, SqliteConnection>, i64, Error> {
+    with_conn(|cn: &mut SqliteConnection| Ok(count(cn, "sqlite_master")))
+}
+
+#[test]
+fn broken_connections_are_retried() {
+    match run_pooled_with_retry(&broken_pool(), 0, select_one()) {
+        Err(Error::Diesel(diesel::result::Error::BrokenTransactionManager)) => (),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    assert_eq!(run_pooled_with_retry(&broken_pool(), 1, select_one()).unwrap(), 0);
+}
+
+#[test]
+fn broken_connections_are_retried_for_propagated_transactions() {
+    assert!(run_pooled_with_retry(&broken_pool(), 0, select_one().required()).is_err());
+    assert_eq!(run_pooled_with_retry(&broken_pool(), 1, select_one().required()).unwrap(), 0);
+}
+
+#[test]
+fn pool_errors_are_converted() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_millis(100))
+        .build(ConnectionManager::<SqliteConnection>::new(":memory:"))
+        .unwrap();
+    let _held = pool.get().unwrap();
+    match run_pooled(&pool, select_one()) {
+        Err(Error::Pool(_)) => (),
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn requires_new_commits_independently() {
+    let pool = Pool::builder()
+        .max_size(2)
+        .build(ConnectionManager::<SqliteConnection>::new(database("requires-new").as_str()))
+        .unwrap();
+    let tx = try_insert(1)
+        .requires_new()
+        .and_then(|_| try_insert(2))
+        .and_then(|_| lazy(|| Err::<(), _>(Error::Fail)));
+    match run_pooled(&pool, tx) {
+        Err(Error::Fail) => (),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    // the outer rollback does not undo the new transaction
+    assert_eq!(count(&mut pool.get().unwrap(), "users"), 1);
+}
+
+#[test]
+fn requires_new_is_unsupported_without_a_pool() {
+    let mut cn = SqliteConnection::establish(&database("requires-new-unsupported")).unwrap();
+    let tx = try_insert(1).and_then(|_| try_insert(2).requires_new());
+    match run(&mut cn, tx) {
+        Err(Error::Propagation(PropagationError::Unsupported)) => (),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    assert_eq!(count(&mut cn, "users"), 0);
+}
@@ -0,0 +1,119 @@
+extern crate diesel;
+extern crate transaction;
+extern crate transaction_diesel;
+
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use diesel::sqlite::SqliteConnection;
+use transaction::prelude::*;
+use transaction::{on_commit, on_rollback};
+use transaction_diesel::{dry_run, run, test_run, with_conn, with_conn_ro, DieselContext};
+
+use common::{connection, count, execute, Error};
+
+fn insert_user<'a>(id: i32) -> impl TxFor<DieselContext<'a, SqliteConnection>, (), Error> {
+    with_conn(move |cn: &mut SqliteConnection| {
+        execute(cn, &format!("INSERT INTO users VALUES ({}, 'user{}')", id, id));
+        Ok(())
+    })
+}
+
+fn fail<'a>() -> impl TxFor<DieselContext<'a, SqliteConnection>, (), Error> {
+    lazy(|| Err(Error::Fail))
+}
+
+#[test]
+fn run_commits_on_success() {
+    let mut cn = connection();
+    let ret = run(&mut cn, insert_user(1).and_then(|_| insert_user(2)));
+    assert!(ret.is_ok());
+    assert_eq!(count(&mut cn, "users"), 2);
+}
+
+#[test]
+fn run_rolls_back_on_failure() {
+    let mut cn = connection();
+    let ret = run(&mut cn, insert_user(1).and_then(|_| fail()));
+    match ret {
+        Err(Error::Fail) => (),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    assert_eq!(count(&mut cn, "users"), 0);
+}
+
+#[test]
+fn test_run_never_commits() {
+    let mut cn = connection();
+    test_run(&mut cn, insert_user(1));
+    assert_eq!(count(&mut cn, "users"), 0);
+}
+
+#[test]
+fn dry_run_returns_the_result_and_rolls_back() {
+    let mut cn = connection();
+    let ret = dry_run(&mut cn, insert_user(1).and_then(|_| {
+        with_conn(|cn: &mut SqliteConnection| Ok(count(cn, "users")))
+    }));
+    assert_eq!(ret.unwrap(), 1);
+    assert_eq!(count(&mut cn, "users"), 0);
+}
+
+// a transaction recording the hooks called into `log`
+fn hooked<'a>(
+    log: &Rc<RefCell<Vec<&'static str>>>,
+    name: &'static str,
+) -> impl TxFor<DieselContext<'a, SqliteConnection>, (), Error> {
+    let (l1, l2) = (log.clone(), log.clone());
+    on_commit(move || l1.borrow_mut().push(name))
+        .and_then(move |_| {
+            let l2 = l2.clone();
+            on_rollback(move || l2.borrow_mut().push("rollback"))
+        })
+}
+
+#[test]
+fn memo_is_cleared_by_writes() {
+    let mut cn = connection();
+    let count_users = || {
+        with_conn_ro(|cn: &mut SqliteConnection| Ok::<_, Error>(count(cn, "users"))).memo_by("users")
+    };
+    let tx = count_users().join(count_users()).and_then(move |before| {
+        insert_user(1)
+            .and_then(move |_| count_users())
+            .map(move |after| (before, after))
+    });
+    assert_eq!(run(&mut cn, tx).unwrap(), ((0, 0), 1));
+}
+
+#[test]
+fn hooks_are_called_after_commit_or_rollback() {
+    let mut cn = connection();
+    let log = Rc::new(RefCell::new(Vec::new()));
+
+    run(&mut cn, hooked(&log, "commit")).unwrap();
+    assert_eq!(*log.borrow(), vec!["commit"]);
+
+    log.borrow_mut().clear();
+    assert!(run(&mut cn, hooked(&log, "commit").and_then(|_| fail())).is_err());
+    assert_eq!(*log.borrow(), vec!["rollback"]);
+
+    log.borrow_mut().clear();
+    dry_run(&mut cn, hooked(&log, "commit")).unwrap();
+    assert_eq!(*log.borrow(), vec!["rollback"]);
+}
+
+#[test]
+fn hooks_of_rolled_back_savepoints_are_dropped() {
+    let mut cn = connection();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let tx = hooked(&log, "outer").and_then({
+        let log = log.clone();
+        move |_| hooked(&log, "inner").and_then(|_| fail()).nested().recover::<Error, _>(|_| ())
+    });
+    run(&mut cn, tx).unwrap();
+    // the inner `on_rollback` is called by the savepoint
+    assert_eq!(*log.borrow(), vec!["rollback", "outer"]);
+}
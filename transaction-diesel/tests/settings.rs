@@ -0,0 +1,129 @@
+#![cfg(feature = "postgres")]
+
+extern crate diesel;
+extern crate transaction;
+extern crate transaction_diesel;
+
+use std::cell::Cell;
+use std::env;
+
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::DatabaseErrorKind;
+use diesel::sql_types::{Nullable, Text};
+use transaction::prelude::*;
+use transaction::PropagationError;
+use transaction_diesel::{run, run_with_settings, set_constraints_deferred, set_local, with_conn, with_conn_ro, PgContext};
+
+#[derive(Debug)]
+#[allow(dead_code)]
+enum Error {
+    Diesel(diesel::result::Error),
+    Propagation(PropagationError),
+    #[cfg(feature = "r2d2")]
+    Pool(diesel::r2d2::PoolError),
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Self {
+        Error::Diesel(e)
+    }
+}
+
+impl From<PropagationError> for Error {
+    fn from(e: PropagationError) -> Self {
+        Error::Propagation(e)
+    }
+}
+
+#[cfg(feature = "r2d2")]
+impl From<diesel::r2d2::PoolError> for Error {
+    fn from(e: diesel::r2d2::PoolError) -> Self {
+        Error::Pool(e)
+    }
+}
+
+fn connection() -> PgConnection {
+    let url = env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+    PgConnection::establish(&url).unwrap()
+}
+
+fn tenant(cn: &mut PgConnection) -> diesel::QueryResult<Option<String>> {
+    diesel::select(sql::<Nullable<Text>>("NULLIF(current_setting('app.tenant', true), '')")).get_result(cn)
+}
+
+fn current_tenant<'a>() -> impl TxFor<PgContext<'a>, Option<String>, Error> {
+    with_conn_ro(|cn: &mut PgConnection| Ok(tenant(cn)?))
+}
+
+#[test]
+#[ignore = "requires a Postgres server at DATABASE_URL"]
+fn set_local_is_seen_until_the_commit() {
+    let mut cn = connection();
+    let tx = set_local("app.tenant", "42").and_then(|_| current_tenant());
+    assert_eq!(run(&mut cn, tx).unwrap(), Some("42".to_string()));
+    assert_eq!(tenant(&mut cn).unwrap(), None);
+}
+
+#[test]
+#[ignore = "requires a Postgres server at DATABASE_URL"]
+fn settings_are_set_in_propagated_transactions() {
+    let mut cn = connection();
+    let settings = [("app.tenant", "42")];
+    let tx = current_tenant().join(current_tenant().nested()).required();
+    let tenants = run_with_settings(&mut cn, &settings, tx).unwrap();
+    assert_eq!(tenants, (Some("42".to_string()), Some("42".to_string())));
+    assert_eq!(tenant(&mut cn).unwrap(), None);
+}
+
+#[cfg(feature = "r2d2")]
+#[test]
+#[ignore = "requires a Postgres server at DATABASE_URL"]
+fn settings_are_set_in_requires_new_of_pooled_runs() {
+    use diesel::r2d2::{ConnectionManager, Pool};
+    use transaction_diesel::run_pooled_with_settings;
+
+    let url = env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+    let pool = Pool::builder()
+        .max_size(2)
+        .build(ConnectionManager::<PgConnection>::new(url))
+        .unwrap();
+    let settings = [("app.tenant", "42")];
+    let tx = current_tenant().join(current_tenant().requires_new());
+    let tenants = run_pooled_with_settings(&pool, &settings, tx).unwrap();
+    assert_eq!(tenants, (Some("42".to_string()), Some("42".to_string())));
+}
+
+#[test]
+#[ignore = "requires a Postgres server at DATABASE_URL"]
+fn deferred_constraints_are_checked_at_the_commit() {
+    let mut cn = connection();
+    cn.batch_execute(
+        "CREATE TEMPORARY TABLE parents (id INTEGER PRIMARY KEY);
+         CREATE TEMPORARY TABLE children (
+             id INTEGER PRIMARY KEY,
+             parent_id INTEGER NOT NULL REFERENCES parents DEFERRABLE INITIALLY IMMEDIATE
+         );",
+    )
+    .unwrap();
+    let insert = |sql: &'static str| with_conn(move |cn: &mut PgConnection| Ok::<_, Error>(cn.batch_execute(sql)?));
+
+    // the parent is inserted after the child
+    let tx = set_constraints_deferred()
+        .and_then(|_| insert("INSERT INTO children VALUES (1, 1)"))
+        .and_then(|_| insert("INSERT INTO parents VALUES (1)"));
+    run(&mut cn, tx).unwrap();
+
+    // the child without the parent is inserted but the commit fails
+    let inserted = Cell::new(false);
+    let tx = set_constraints_deferred()
+        .and_then(|_| insert("INSERT INTO children VALUES (2, 2)"))
+        .map(|_| inserted.set(true));
+    match run(&mut cn, tx) {
+        Err(Error::Diesel(diesel::result::Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _))) => (),
+        r => panic!("unexpected result: {:?}", r),
+    }
+    assert!(inserted.get());
+}
@@ -0,0 +1,165 @@
+extern crate diesel;
+extern crate transaction;
+extern crate transaction_diesel;
+
+mod common;
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use transaction::prelude::*;
+use transaction_diesel::{load, persist, remove, run, DieselContext, Entity};
+
+use common::{connection, count, execute, Error};
+
+table! {
+    users (id) {
+        id -> Integer,
+        name -> Text,
+    }
+}
+
+table! {
+    posts (id) {
+        id -> Integer,
+        user_id -> Integer,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = users)]
+struct User {
+    id: i32,
+    name: String,
+}
+
+impl Entity<SqliteConnection> for User {
+    type Id = i32;
+
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn find(conn: &mut SqliteConnection, id: &i32) -> QueryResult<Option<Self>> {
+        users::table.find(*id).first(conn).optional()
+    }
+
+    fn insert(&self, conn: &mut SqliteConnection) -> QueryResult<()> {
+        diesel::insert_into(users::table).values(self).execute(conn).map(|_| ())
+    }
+
+    fn update(&self, conn: &mut SqliteConnection) -> QueryResult<()> {
+        diesel::update(users::table.find(self.id)).set(self).execute(conn).map(|_| ())
+    }
+
+    fn delete(id: &i32, conn: &mut SqliteConnection) -> QueryResult<()> {
+        diesel::delete(users::table.find(*id)).execute(conn).map(|_| ())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Queryable, Insertable)]
+#[diesel(table_name = posts)]
+struct Post {
+    id: i32,
+    user_id: i32,
+}
+
+impl Entity<SqliteConnection> for Post {
+    type Id = i32;
+
+    // references users
+    const ORDER: i32 = 1;
+
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn find(conn: &mut SqliteConnection, id: &i32) -> QueryResult<Option<Self>> {
+        posts::table.find(*id).first(conn).optional()
+    }
+
+    fn insert(&self, conn: &mut SqliteConnection) -> QueryResult<()> {
+        diesel::insert_into(posts::table).values(self).execute(conn).map(|_| ())
+    }
+
+    fn update(&self, conn: &mut SqliteConnection) -> QueryResult<()> {
+        diesel::update(posts::table.find(self.id))
+            .set(posts::user_id.eq(self.user_id))
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    fn delete(id: &i32, conn: &mut SqliteConnection) -> QueryResult<()> {
+        diesel::delete(posts::table.find(*id)).execute(conn).map(|_| ())
+    }
+}
+
+fn name(cn: &mut SqliteConnection, id: i32) -> String {
+    users::table.find(id).select(users::name).first(cn).unwrap()
+}
+
+fn rename<'a>(id: i32, name: &'static str) -> impl TxFor<DieselContext<'a, SqliteConnection>, (), Error> {
+    load::<User, _, Error>(id).map(move |user| user.unwrap().borrow_mut().name = name.to_string())
+}
+
+fn fail<'a>() -> impl TxFor<DieselContext<'a, SqliteConnection>, (), Error> {
+    lazy(|| Err(Error::Fail))
+}
+
+#[test]
+fn changes_are_flushed_at_the_commit() {
+    let mut cn = connection();
+    execute(&mut cn, "INSERT INTO users VALUES (1, 'old'), (2, 'removed')");
+    let tx = rename(1, "new")
+        .and_then(|_| persist(User { id: 3, name: "persisted".to_string() }))
+        .and_then(|_| remove::<User, _, Error>(2))
+        .and_then(|_| load::<User, _, Error>(2));
+    let removed = run(&mut cn, tx).unwrap();
+    assert!(removed.is_none());
+    assert_eq!(name(&mut cn, 1), "new");
+    assert_eq!(name(&mut cn, 3), "persisted");
+    assert_eq!(count(&mut cn, "users"), 2);
+}
+
+#[test]
+fn entities_are_tracked_after_a_rolled_back_savepoint() {
+    let mut cn = connection();
+    execute(&mut cn, "INSERT INTO users VALUES (1, 'old'), (2, 'old')");
+    let tx = load::<User, _, Error>(1).and_then(|user| {
+        let user = user.unwrap();
+        rename(1, "inner")
+            .and_then(|_| rename(2, "inner"))
+            .and_then(|_| fail())
+            .nested()
+            .recover::<Error, _>(|_| ())
+            .map(move |_| {
+                // restored to the value at the savepoint
+                assert_eq!(user.borrow().name, "old");
+                user.borrow_mut().name = "new".to_string();
+            })
+    });
+    run(&mut cn, tx).unwrap();
+    assert_eq!(name(&mut cn, 1), "new");
+    assert_eq!(name(&mut cn, 2), "old");
+}
+
+#[test]
+fn entities_are_flushed_in_order() {
+    let mut cn = connection();
+    execute(&mut cn, "PRAGMA foreign_keys = ON");
+    execute(
+        &mut cn,
+        "CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER NOT NULL REFERENCES users (id))",
+    );
+
+    // users are inserted before the posts referencing them
+    let tx = persist::<_, _, Error>(Post { id: 1, user_id: 1 })
+        .and_then(|_| persist(User { id: 1, name: "user".to_string() }));
+    run(&mut cn, tx).unwrap();
+    assert_eq!(count(&mut cn, "posts"), 1);
+
+    // and deleted after them
+    let tx = remove::<User, _, Error>(1).and_then(|_| remove::<Post, _, Error>(1));
+    run(&mut cn, tx).unwrap();
+    assert_eq!(count(&mut cn, "users"), 0);
+    assert_eq!(count(&mut cn, "posts"), 0);
+}
@@ -0,0 +1,1007 @@
+//! Procedural macros for transaction
+//!
+//! Use them via the `macros` feature of `transaction`.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use std::collections::HashSet;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenTree};
+use syn::visit::Visit;
+use syn::visit_mut::VisitMut;
+use syn::{Block, Error, Expr, ExprMacro, FnArg, GenericArgument, GenericParam, Ident, ItemFn,
+          Lifetime, LifetimeParam, Local, Macro, Pat, PathArguments, ReturnType, Stmt, Type};
+
+/// Turn a function returning `Result<T, E>` into a function returning
+/// `impl Transaction<Ctx = ctx, Item = T, Err = E>`.
+///
+/// In the body, `expr?` runs the transaction `expr` under the current
+/// context and propagates its error as usual. `expr?` also accepts plain
+/// `Result`s. Sequencing, `if`, `match` and loops are written as ordinary
+/// Rust.
+///
+/// ```ignore
+/// #[transactional(ctx = DieselContext<'a, PgConnection>)]
+/// fn transfer(from: i64, to: i64, amount: i64) -> Result<(), Error> {
+///     let balance = find_balance(from)?;
+///     if balance < amount {
+///         return Err(Error::InsufficientBalance);
+///     }
+///     withdraw(from, amount)?;
+///     deposit(to, amount)?;
+///     Ok(())
+/// }
+/// ```
+///
+/// The body is rewritten into combinators: the statements after
+/// `let x = expr?;` or `expr?;` become the closure of `and_then`, `if` and
+/// `match` become `branch`es and loops become `loop_fn`. Expressions having
+/// `?` inside, such as `f(expr?)`, are run as `with_ctx` leaves. As the body is
+/// built at each run, `describe` shows only the name of the function.
+///
+/// Because a transaction can be run more than once, the arguments and the
+/// local variables used after `?` are cloned and must be `Clone`, and so are
+/// the return type and the error type. Mutable
+/// variables are passed along to the rest of the body. The transactions of
+/// `?` are run after they are built, so they cannot borrow local variables.
+/// `?` inside closures and macro invocations in the body is left as is.
+/// Labeled `break`s and `continue`s, and `break`s with values, are not
+/// supported across `?`. Methods (functions with `self`) are not supported.
+#[proc_macro_attribute]
+pub fn transactional(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item = syn::parse_macro_input!(item as ItemFn);
+    let result = ctx_type(attr.into()).and_then(|ctx| transactional_fn(ctx, item));
+    match result {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+// parse `ctx = Type`
+fn ctx_type(attr: proc_macro2::TokenStream) -> syn::Result<Type> {
+    let mut ctx = None;
+    let parser = syn::meta::parser(|meta| if meta.path.is_ident("ctx") {
+        ctx = Some(meta.value()?.parse()?);
+        Ok(())
+    } else {
+        Err(meta.error("expected `ctx`"))
+    });
+    syn::parse::Parser::parse2(parser, attr)?;
+    ctx.ok_or_else(|| {
+        Error::new(
+            Span::call_site(),
+            "specify the context type by `#[transactional(ctx = ...)]`",
+        )
+    })
+}
+
+// split `Result<T, E>` into `T` and `E`
+fn result_type(ret: &ReturnType) -> syn::Result<(Type, Type)> {
+    let ty = match *ret {
+        ReturnType::Type(_, ref ty) => &**ty,
+        ReturnType::Default => {
+            return Err(Error::new(
+                Span::call_site(),
+                "#[transactional] functions must return `Result<T, E>`",
+            ))
+        }
+    };
+    if let Type::Path(ref p) = *ty {
+        if let Some(last) = p.path.segments.last() {
+            if let PathArguments::AngleBracketed(ref args) = last.arguments {
+                let tys: Vec<_> = args.args
+                    .iter()
+                    .filter_map(|arg| match *arg {
+                        GenericArgument::Type(ref ty) => Some(ty.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                if last.ident == "Result" && tys.len() == 2 {
+                    return Ok((tys[0].clone(), tys[1].clone()));
+                }
+            }
+        }
+    }
+    Err(Error::new_spanned(
+        ty,
+        "#[transactional] functions must return `Result<T, E>`",
+    ))
+}
+
+// collect the lifetimes appearing in a type
+struct Lifetimes(Vec<Lifetime>);
+
+impl<'ast> Visit<'ast> for Lifetimes {
+    fn visit_lifetime(&mut self, lt: &'ast Lifetime) {
+        if lt.ident != "static" && !self.0.contains(lt) {
+            self.0.push(lt.clone());
+        }
+    }
+}
+
+// name the elided lifetimes of the arguments so that the returned
+// transaction can capture them
+struct NameLifetimes(Vec<Lifetime>);
+
+impl NameLifetimes {
+    fn fresh(&mut self) -> Lifetime {
+        let lt = Lifetime::new(&format!("'__arg{}", self.0.len()), Span::call_site());
+        self.0.push(lt.clone());
+        lt
+    }
+}
+
+impl VisitMut for NameLifetimes {
+    fn visit_type_reference_mut(&mut self, r: &mut syn::TypeReference) {
+        if r.lifetime.is_none() {
+            r.lifetime = Some(self.fresh());
+        }
+        syn::visit_mut::visit_type_reference_mut(self, r);
+    }
+
+    fn visit_lifetime_mut(&mut self, lt: &mut Lifetime) {
+        if lt.ident == "_" {
+            *lt = self.fresh();
+        }
+    }
+
+    // they have their own elision rules
+    fn visit_type_bare_fn_mut(&mut self, _: &mut syn::TypeBareFn) {}
+    fn visit_parenthesized_generic_arguments_mut(
+        &mut self,
+        _: &mut syn::ParenthesizedGenericArguments,
+    ) {
+    }
+}
+
+// what an expression does to the control flow of the body
+#[derive(Default)]
+struct Effects {
+    // has `?`
+    step: bool,
+    // has `return`, or `break` or `continue` leaving the expression
+    exit: bool,
+    // has labeled `break` or `continue`, or `break` with a value leaving the
+    // expression
+    unsupported: bool,
+}
+
+impl Effects {
+    fn of_expr(e: &Expr) -> Self {
+        let mut v = EffectsVisitor::default();
+        v.visit_expr(e);
+        v.effects
+    }
+
+    fn of_local(local: &Local) -> Self {
+        let mut v = EffectsVisitor::default();
+        v.visit_local(local);
+        v.effects
+    }
+
+    fn pure(&self) -> bool {
+        !self.step && !self.exit && !self.unsupported
+    }
+}
+
+#[derive(Default)]
+struct EffectsVisitor {
+    effects: Effects,
+    // the loops and labeled blocks entered, whether it is a loop and its label
+    scopes: Vec<(bool, Option<Lifetime>)>,
+}
+
+impl EffectsVisitor {
+    fn leave(&mut self, label: Option<&Lifetime>, value: bool) {
+        match label {
+            Some(l) => if !self.scopes.iter().any(|s| s.1.as_ref() == Some(l)) {
+                self.effects.unsupported = true;
+            },
+            None => if !self.scopes.iter().any(|s| s.0) {
+                if value {
+                    self.effects.unsupported = true;
+                } else {
+                    self.effects.exit = true;
+                }
+            },
+        }
+    }
+
+    fn enter<F>(&mut self, is_loop: bool, label: Option<&syn::Label>, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        self.scopes.push((is_loop, label.map(|l| l.name.clone())));
+        f(self);
+        self.scopes.pop();
+    }
+}
+
+impl<'ast> Visit<'ast> for EffectsVisitor {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        match *e {
+            // they have their own `?` and `return`
+            Expr::Closure(_) | Expr::Async(_) => return,
+            Expr::Try(_) => self.effects.step = true,
+            Expr::Return(_) => self.effects.exit = true,
+            Expr::Break(ref b) => self.leave(b.label.as_ref(), b.expr.is_some()),
+            Expr::Continue(ref c) => self.leave(c.label.as_ref(), false),
+            Expr::Loop(ref l) => {
+                return self.enter(true, l.label.as_ref(), |v| syn::visit::visit_expr_loop(v, l))
+            }
+            Expr::While(ref w) => {
+                return self.enter(true, w.label.as_ref(), |v| syn::visit::visit_expr_while(v, w))
+            }
+            Expr::ForLoop(ref f) => {
+                return self.enter(true, f.label.as_ref(), |v| {
+                    syn::visit::visit_expr_for_loop(v, f)
+                })
+            }
+            Expr::Block(ref b) if b.label.is_some() => {
+                return self.enter(false, b.label.as_ref(), |v| {
+                    syn::visit::visit_expr_block(v, b)
+                })
+            }
+            _ => (),
+        }
+        syn::visit::visit_expr(self, e);
+    }
+
+    fn visit_macro(&mut self, m: &'ast Macro) {
+        let name = m.path.segments.last().map(|s| s.ident.to_string());
+        match name.as_deref() {
+            Some("try") | Some("bail") | Some("ensure") => {
+                self.effects.step = true;
+                self.effects.exit = true;
+            }
+            _ => (),
+        }
+        if has_question(m.tokens.clone()) {
+            self.effects.step = true;
+        }
+    }
+
+    fn visit_item(&mut self, _item: &'ast syn::Item) {
+        // nested items are not part of the transaction
+    }
+}
+
+fn has_question(tokens: proc_macro2::TokenStream) -> bool {
+    tokens.into_iter().any(|t| match t {
+        TokenTree::Punct(ref p) => p.as_char() == '?',
+        TokenTree::Group(ref g) => has_question(g.stream()),
+        _ => false,
+    })
+}
+
+// whether the expression is rewritten into combinators
+fn structured(e: &Expr) -> bool {
+    let effects = Effects::of_expr(e);
+    if effects.pure() || effects.unsupported {
+        return false;
+    }
+    match *e {
+        Expr::If(ref i) => Effects::of_expr(&i.cond).pure(),
+        Expr::Match(ref m) => {
+            Effects::of_expr(&m.expr).pure() &&
+                m.arms.iter().all(|a| {
+                    a.guard.as_ref().is_none_or(|g| Effects::of_expr(&g.1).pure())
+                })
+        }
+        Expr::Block(ref b) => b.label.is_none(),
+        Expr::Loop(ref l) => l.label.is_none(),
+        Expr::While(ref w) => w.label.is_none() && Effects::of_expr(&w.cond).pure(),
+        Expr::ForLoop(ref f) => f.label.is_none() && Effects::of_expr(&f.expr).pure(),
+        _ => false,
+    }
+}
+
+// the variables bound by a pattern, and whether they are mutable
+struct Bindings(Vec<(Ident, bool)>);
+
+impl<'ast> Visit<'ast> for Bindings {
+    fn visit_pat_ident(&mut self, p: &'ast syn::PatIdent) {
+        // upper case identifiers are unit structs, variants or constants
+        let name = p.ident.to_string();
+        if !name.starts_with(|c: char| c.is_uppercase()) {
+            self.0.push((p.ident.clone(), p.mutability.is_some()));
+        }
+        syn::visit::visit_pat_ident(self, p);
+    }
+
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        // the patterns of `if let` and `while let`
+        match *e {
+            Expr::Let(ref l) => self.visit_pat(&l.pat),
+            Expr::Binary(ref b) => {
+                self.visit_expr(&b.left);
+                self.visit_expr(&b.right);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn bindings_of_pat(pat: &Pat) -> Vec<(Ident, bool)> {
+    let mut b = Bindings(Vec::new());
+    b.visit_pat(pat);
+    b.0
+}
+
+// the local variables in scope
+#[derive(Clone, Default)]
+struct Scope(Vec<(Ident, bool)>);
+
+impl Scope {
+    fn bind(&mut self, pat: &Pat) {
+        self.0.extend(bindings_of_pat(pat));
+    }
+
+    fn bind_cond(&mut self, cond: &Expr) {
+        let mut b = Bindings(Vec::new());
+        b.visit_expr(cond);
+        self.0.extend(b.0);
+    }
+
+    // the variables not shadowed
+    fn visible(&self) -> Vec<(Ident, bool)> {
+        let mut seen = HashSet::new();
+        let mut vars: Vec<_> = self.0
+            .iter()
+            .rev()
+            .filter(|v| seen.insert(v.0.to_string()))
+            .cloned()
+            .collect();
+        vars.reverse();
+        vars
+    }
+
+    fn muts(&self) -> Vec<Ident> {
+        self.visible()
+            .into_iter()
+            .filter(|v| v.1)
+            .map(|v| v.0)
+            .collect()
+    }
+}
+
+// the identifiers used as variables in the tokens
+fn used_idents(tokens: proc_macro2::TokenStream, used: &mut HashSet<String>) {
+    let mut prev: Option<char> = None;
+    for t in tokens {
+        prev = match t {
+            TokenTree::Ident(ref i) => {
+                // skip fields, methods, paths, types and lifetimes
+                match prev {
+                    Some('.') | Some(':') | Some('\'') => (),
+                    _ => {
+                        used.insert(i.to_string());
+                    }
+                }
+                None
+            }
+            TokenTree::Punct(ref p) => Some(p.as_char()),
+            TokenTree::Group(ref g) => {
+                used_idents(g.stream(), used);
+                None
+            }
+            TokenTree::Literal(_) => None,
+        };
+    }
+}
+
+// how the end of the rewritten block is reached
+#[derive(Clone)]
+enum Frame {
+    // the body of the function. The transaction returns the result of it
+    Body,
+    // a block inside the body. The transaction returns the mutable variables
+    // and `Flow`
+    Block(Vec<Ident>),
+}
+
+// rewrite `expr?` into `Bind::bind(expr, ctx)?`. Inside the leaves of
+// blocks, `return`, `break` and `continue` leave the leaf with `Flow`.
+struct Leaf {
+    ctx: Ident,
+    muts: Option<Vec<Ident>>,
+    loops: usize,
+}
+
+impl VisitMut for Leaf {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        match *expr {
+            // they have their own `?`
+            Expr::Closure(_) | Expr::Async(_) => return,
+            Expr::Loop(_) | Expr::While(_) | Expr::ForLoop(_) => {
+                self.loops += 1;
+                syn::visit_mut::visit_expr_mut(self, expr);
+                self.loops -= 1;
+                return;
+            }
+            _ => (),
+        }
+        syn::visit_mut::visit_expr_mut(self, expr);
+        if let Expr::Try(ref mut e) = *expr {
+            let inner = &e.expr;
+            let ctx = &self.ctx;
+            *e.expr = syn::parse_quote! {
+                ::transaction::__private::Bind::bind(#inner, &mut *#ctx)
+            };
+            return;
+        }
+        let muts = match self.muts {
+            Some(ref muts) => muts,
+            None => return,
+        };
+        let flow = match *expr {
+            Expr::Return(ref r) => {
+                let r = &r.expr;
+                *expr = syn::parse_quote! {
+                    return ::transaction::__private::returned(#r, (#(#muts,)*))
+                };
+                return;
+            }
+            Expr::Break(_) if self.loops == 0 => quote!(Break),
+            Expr::Continue(_) if self.loops == 0 => quote!(Continue),
+            _ => return,
+        };
+        *expr = syn::parse_quote! {
+            return ::std::result::Result::Ok((
+                (#(#muts,)*),
+                ::transaction::__private::Flow::#flow,
+            ))
+        };
+    }
+
+    fn visit_item_mut(&mut self, _item: &mut syn::Item) {
+        // nested items are not part of the transaction
+    }
+}
+
+// rewrite a function body into a transaction
+struct Rewrite {
+    ctx: Type,
+    item_ty: Type,
+    err_ty: Type,
+    ctx_ident: Ident,
+    iters: usize,
+}
+
+impl Rewrite {
+    fn ident(name: &str) -> Ident {
+        Ident::new(name, Span::mixed_site())
+    }
+
+    // a closure capturing clones of the variables, as it can be called many times
+    fn closure(
+        &self,
+        head: proc_macro2::TokenStream,
+        body: proc_macro2::TokenStream,
+        scope: &Scope,
+        exclude: &[Ident],
+    ) -> proc_macro2::TokenStream {
+        let mut used = HashSet::new();
+        used_idents(body.clone(), &mut used);
+        let captured: Vec<_> = scope
+            .visible()
+            .into_iter()
+            .filter(|v| used.contains(&v.0.to_string()) && !exclude.contains(&v.0))
+            .collect();
+        if captured.is_empty() {
+            return quote!(move #head { #body });
+        }
+        let outer = captured.iter().map(|v| &v.0);
+        let inner = captured.iter().map(|&(ref i, m)| {
+            let m = if m { quote!(mut) } else { quote!() };
+            quote! {
+                #[allow(unused_mut)]
+                let #m #i = ::std::clone::Clone::clone(&#i);
+            }
+        });
+        quote! {{
+            #(let #outer = ::std::clone::Clone::clone(&#outer);)*
+            move #head {
+                #(#inner)*
+                #body
+            }
+        }}
+    }
+
+    fn ready(&self, value: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        let Rewrite { ref ctx, ref err_ty, .. } = *self;
+        quote! {
+            ::transaction::__private::ready::<#ctx, _, #err_ty>(::std::result::Result::Ok(#value))
+        }
+    }
+
+    fn block(&mut self, stmts: &[Stmt], scope: &Scope, frame: &Frame) -> syn::Result<proc_macro2::TokenStream> {
+        let mut scope = scope.clone();
+        let mut pre = Vec::new();
+        for (i, stmt) in stmts.iter().enumerate() {
+            let rest = &stmts[i + 1..];
+            let tx = match *stmt {
+                Stmt::Local(ref local) => self.local(local, rest, &mut scope, frame)?,
+                Stmt::Expr(ref e, None) if rest.is_empty() => Some(self.tail(e, &scope, frame)?),
+                Stmt::Expr(ref e, _) => self.stmt(e, rest, &scope, frame)?,
+                Stmt::Macro(ref m) => {
+                    let e = Expr::Macro(ExprMacro {
+                        attrs: m.attrs.clone(),
+                        mac: m.mac.clone(),
+                    });
+                    if m.semi_token.is_none() && rest.is_empty() {
+                        Some(self.tail(&e, &scope, frame)?)
+                    } else {
+                        self.stmt(&e, rest, &scope, frame)?
+                    }
+                }
+                Stmt::Item(_) => None,
+            };
+            match (tx, stmt) {
+                (Some(tx), _) => return Ok(quote!({ #(#pre)* #tx })),
+                // the variables are mutated in the closures, on their copies
+                (None, Stmt::Local(local)) if bindings_of_pat(&local.pat).iter().any(|b| b.1) => {
+                    pre.push(quote!(#[allow(unused_mut)] #stmt))
+                }
+                (None, stmt) => pre.push(quote!(#stmt)),
+            }
+        }
+        let end = match *frame {
+            Frame::Body => {
+                let Rewrite { ref ctx, ref item_ty, ref err_ty, .. } = *self;
+                quote!(::transaction::__private::unreachable::<#ctx, #item_ty, #err_ty>())
+            }
+            Frame::Block(ref muts) => {
+                self.ready(quote!(((#(#muts,)*), ::transaction::__private::Flow::Next(()))))
+            }
+        };
+        Ok(quote!({ #(#pre)* #end }))
+    }
+
+    // `let`. Returns `None` if it does not need rewriting
+    fn local(
+        &mut self,
+        local: &Local,
+        rest: &[Stmt],
+        scope: &mut Scope,
+        frame: &Frame,
+    ) -> syn::Result<Option<proc_macro2::TokenStream>> {
+        let init = match local.init {
+            Some(ref init) if !Effects::of_local(local).pure() => init,
+            _ => {
+                scope.bind(&local.pat);
+                return Ok(None);
+            }
+        };
+        if init.diverge.is_some() {
+            return Err(Error::new_spanned(
+                local,
+                "#[transactional] does not support `let ... else` with `?`",
+            ));
+        }
+        self.bind(&init.expr, &local.pat, rest, scope, frame).map(Some)
+    }
+
+    // `let pat = e;` followed by `rest`
+    fn bind(
+        &mut self,
+        e: &Expr,
+        pat: &Pat,
+        rest: &[Stmt],
+        scope: &Scope,
+        frame: &Frame,
+    ) -> syn::Result<proc_macro2::TokenStream> {
+        match *e {
+            Expr::Try(ref t) if Effects::of_expr(&t.expr).pure() => {
+                self.step(&t.expr, pat, rest, scope, frame)
+            }
+            ref e if structured(e) => self.structured(e, Some(pat), rest, scope, frame),
+            ref e => self.leaf(e, Some(pat), rest, scope, frame),
+        }
+    }
+
+    // an expression statement. Returns `None` if it does not need rewriting
+    fn stmt(
+        &mut self,
+        e: &Expr,
+        rest: &[Stmt],
+        scope: &Scope,
+        frame: &Frame,
+    ) -> syn::Result<Option<proc_macro2::TokenStream>> {
+        if Effects::of_expr(e).pure() {
+            return Ok(None);
+        }
+        let tx = match *e {
+            Expr::Try(ref t) if Effects::of_expr(&t.expr).pure() => {
+                self.step(&t.expr, &syn::parse_quote!(_), rest, scope, frame)?
+            }
+            ref e => match self.exit_in(e, frame) {
+                Some(tx) => tx?,
+                None if structured(e) => self.structured(e, None, rest, scope, frame)?,
+                None => self.leaf(e, None, rest, scope, frame)?,
+            },
+        };
+        Ok(Some(tx))
+    }
+
+    // the value of a block
+    fn tail(&mut self, e: &Expr, scope: &Scope, frame: &Frame) -> syn::Result<proc_macro2::TokenStream> {
+        if let Some(tx) = self.exit_in(e, frame) {
+            return tx;
+        }
+        let branches = match *e {
+            Expr::If(_) | Expr::Match(_) | Expr::Block(_) => structured(e),
+            _ => false,
+        };
+        if branches {
+            return self.branches(e, scope, frame);
+        }
+        match *frame {
+            Frame::Body => {
+                let Rewrite { ref ctx, ref err_ty, ref item_ty, ref ctx_ident, .. } = *self;
+                if Effects::of_expr(e).pure() {
+                    Ok(quote!(::transaction::__private::ready::<#ctx, #item_ty, #err_ty>(#e)))
+                } else if structured(e) {
+                    self.structured(e, None, &[], scope, frame)
+                } else {
+                    let mut e = e.clone();
+                    Leaf {
+                        ctx: ctx_ident.clone(),
+                        muts: None,
+                        loops: 0,
+                    }.visit_expr_mut(&mut e);
+                    let head = quote! {
+                        |#ctx_ident: &mut #ctx| -> ::std::result::Result<#item_ty, #err_ty>
+                    };
+                    let f = self.closure(head, quote!(#e), scope, &[]);
+                    Ok(quote!(::transaction::with_ctx(#f)))
+                }
+            }
+            Frame::Block(ref muts) => {
+                if Effects::of_expr(e).pure() {
+                    Ok(self.ready(quote!(((#(#muts,)*), ::transaction::__private::Flow::Next(#e)))))
+                } else {
+                    let value = Rewrite::ident("__value");
+                    let rest = [Stmt::Expr(syn::parse_quote!(#value), None)];
+                    self.bind(e, &syn::parse_quote!(#value), &rest, scope, frame)
+                }
+            }
+        }
+    }
+
+    // `return`, `break` and `continue`. Returns `None` for the others
+    fn exit_in(&self, e: &Expr, frame: &Frame) -> Option<syn::Result<proc_macro2::TokenStream>> {
+        let Rewrite { ref ctx, ref item_ty, ref err_ty, .. } = *self;
+        let flow = match *e {
+            Expr::Return(ref r) => {
+                let r = match r.expr {
+                    Some(ref r) if Effects::of_expr(r).pure() => r,
+                    _ => return None,
+                };
+                return Some(Ok(match *frame {
+                    Frame::Body => quote! {
+                        ::transaction::__private::ready::<#ctx, #item_ty, #err_ty>(#r)
+                    },
+                    Frame::Block(ref muts) => quote! {
+                        ::transaction::__private::ret::<#ctx, _, _, #item_ty, #err_ty>(
+                            #r,
+                            (#(#muts,)*),
+                        )
+                    },
+                }));
+            }
+            Expr::Break(ref b) if b.label.is_none() && b.expr.is_none() => quote!(Break),
+            Expr::Continue(ref c) if c.label.is_none() => quote!(Continue),
+            _ => return None,
+        };
+        Some(match *frame {
+            Frame::Body => Err(Error::new_spanned(e, "`break` or `continue` outside of a loop")),
+            Frame::Block(ref muts) => {
+                Ok(self.ready(quote!(((#(#muts,)*), ::transaction::__private::Flow::#flow))))
+            }
+        })
+    }
+
+    // `let pat = x?;` followed by `rest`
+    fn step(
+        &mut self,
+        x: &Expr,
+        pat: &Pat,
+        rest: &[Stmt],
+        scope: &Scope,
+        frame: &Frame,
+    ) -> syn::Result<proc_macro2::TokenStream> {
+        let mut inner = scope.clone();
+        inner.bind(pat);
+        let body = self.block(rest, &inner, frame)?;
+        let exclude: Vec<_> = bindings_of_pat(pat).into_iter().map(|b| b.0).collect();
+        let f = self.closure(quote!(|#pat|), body, scope, &exclude);
+        let Rewrite { ref ctx, ref err_ty, .. } = *self;
+        Ok(quote! {
+            ::transaction::Transaction::and_then(
+                ::transaction::__private::step::<#ctx, #err_ty, _>(#x),
+                #f,
+            )
+        })
+    }
+
+    // `let pat = e;` or `e;` rewritten into combinators, followed by `rest`
+    fn structured(
+        &mut self,
+        e: &Expr,
+        pat: Option<&Pat>,
+        rest: &[Stmt],
+        scope: &Scope,
+        frame: &Frame,
+    ) -> syn::Result<proc_macro2::TokenStream> {
+        let muts = scope.muts();
+        let tx = match *e {
+            Expr::Loop(ref l) => self.loop_fn(&l.body, scope, &muts)?,
+            Expr::While(ref w) => {
+                let cond = &w.cond;
+                let body = &w.body;
+                let l: syn::ExprLoop = syn::parse_quote!(loop { if #cond #body else { break } });
+                self.loop_fn(&l.body, scope, &muts)?
+            }
+            Expr::ForLoop(ref f) => {
+                let iter = Rewrite::ident(&format!("__iter{}", self.iters));
+                self.iters += 1;
+                let (fpat, expr, body) = (&f.pat, &f.expr, &f.body);
+                let b: Block = syn::parse_quote!({
+                    let mut #iter = ::std::iter::IntoIterator::into_iter(#expr);
+                    loop {
+                        match ::std::iter::Iterator::next(&mut #iter) {
+                            ::std::option::Option::Some(#fpat) => #body,
+                            ::std::option::Option::None => break,
+                        }
+                    }
+                });
+                self.block(&b.stmts, scope, &Frame::Block(muts.clone()))?
+            }
+            ref e => self.branches(e, scope, &Frame::Block(muts.clone()))?,
+        };
+        let cont = self.cont(pat, rest, scope, frame, &muts)?;
+        Ok(quote!(::transaction::Transaction::and_then(#tx, #cont)))
+    }
+
+    // the closure receiving the mutable variables and `Flow` of a block
+    fn cont(
+        &mut self,
+        pat: Option<&Pat>,
+        rest: &[Stmt],
+        scope: &Scope,
+        frame: &Frame,
+        muts: &[Ident],
+    ) -> syn::Result<proc_macro2::TokenStream> {
+        // the type of `let pat: ty = ...` is given to `Flow`
+        let (value, ty) = match pat {
+            Some(Pat::Type(p)) => {
+                let ty = &p.ty;
+                ((*p.pat).clone(), quote!(#ty))
+            }
+            Some(p) => (p.clone(), quote!(_)),
+            None => (syn::parse_quote!(_), quote!(_)),
+        };
+        let mut inner = scope.clone();
+        inner.bind(&value);
+        let rest = self.block(rest, &inner, frame)?;
+        let Rewrite { ref ctx, ref err_ty, ref item_ty, .. } = *self;
+        let (muts_ident, flow) = (Rewrite::ident("__muts"), Rewrite::ident("__flow"));
+        let exit = match *frame {
+            Frame::Body => quote!(::transaction::__private::exit::<#ctx, _, _, #err_ty>(#flow)),
+            Frame::Block(ref outer) => self.ready(quote!(((#(#outer,)*), #flow.exit()))),
+        };
+        let body = quote! {
+            #[allow(unused_mut)]
+            let (#(mut #muts,)*) = #muts_ident;
+            match #flow {
+                ::transaction::__private::Flow::Next(#value) => ::transaction::Branch::B1(#rest),
+                #flow => ::transaction::Branch::B2(#exit),
+            }
+        };
+        let head = quote! {
+            |(#muts_ident, #flow): (_, ::transaction::__private::Flow<#ty, #item_ty>)|
+        };
+        let mut exclude = muts.to_vec();
+        exclude.extend(bindings_of_pat(&value).into_iter().map(|b| b.0));
+        Ok(self.closure(head, body, scope, &exclude))
+    }
+
+    // `loop` with the mutable variables as the state
+    fn loop_fn(&mut self, body: &Block, scope: &Scope, muts: &[Ident]) -> syn::Result<proc_macro2::TokenStream> {
+        let body = self.block(&body.stmts, scope, &Frame::Block(muts.to_vec()))?;
+        let muts_ident = Rewrite::ident("__muts");
+        let body = quote! {
+            #[allow(unused_mut)]
+            let (#(mut #muts,)*) = #muts_ident;
+            ::transaction::Transaction::map(#body, ::transaction::__private::iterate)
+        };
+        let f = self.closure(quote!(|#muts_ident|), body, scope, muts);
+        Ok(quote!(::transaction::loop_fn((#(#muts,)*), #f)))
+    }
+
+    // `if`, `match` and blocks as `branch`es
+    fn branches(&mut self, e: &Expr, scope: &Scope, frame: &Frame) -> syn::Result<proc_macro2::TokenStream> {
+        match *e {
+            Expr::If(ref i) => {
+                let mut then_scope = scope.clone();
+                then_scope.bind_cond(&i.cond);
+                let then = self.block(&i.then_branch.stmts, &then_scope, frame)?;
+                let els = match i.else_branch {
+                    None => self.block(&[], scope, frame)?,
+                    Some((_, ref e)) => self.arm(e, scope, frame)?,
+                };
+                let cond = &i.cond;
+                Ok(quote! {
+                    if #cond {
+                        ::transaction::Branch::B1(#then)
+                    } else {
+                        ::transaction::Branch::B2(#els)
+                    }
+                })
+            }
+            Expr::Match(ref m) => {
+                let n = m.arms.len();
+                let mut arms = Vec::new();
+                for (i, arm) in m.arms.iter().enumerate() {
+                    let mut arm_scope = scope.clone();
+                    arm_scope.bind(&arm.pat);
+                    let mut body = self.arm(&arm.body, &arm_scope, frame)?;
+                    if i + 1 < n {
+                        body = quote!(::transaction::Branch::B1(#body));
+                    }
+                    for _ in 0..i {
+                        body = quote!(::transaction::Branch::B2(#body));
+                    }
+                    let (attrs, pat) = (&arm.attrs, &arm.pat);
+                    let guard = arm.guard.as_ref().map(|g| {
+                        let g = &g.1;
+                        quote!(if #g)
+                    });
+                    arms.push(quote!(#(#attrs)* #pat #guard => #body,));
+                }
+                let expr = &m.expr;
+                Ok(quote!(match #expr { #(#arms)* }))
+            }
+            Expr::Block(ref b) => self.block(&b.block.stmts, scope, frame),
+            ref e => self.block(&[Stmt::Expr(e.clone(), None)], scope, frame),
+        }
+    }
+
+    // the body of a branch
+    fn arm(&mut self, e: &Expr, scope: &Scope, frame: &Frame) -> syn::Result<proc_macro2::TokenStream> {
+        match *e {
+            Expr::Block(ref b) if b.label.is_none() => self.block(&b.block.stmts, scope, frame),
+            ref e => self.block(&[Stmt::Expr(e.clone(), None)], scope, frame),
+        }
+    }
+
+    // a statement run as a `with_ctx`, followed by `rest`
+    fn leaf(
+        &mut self,
+        e: &Expr,
+        pat: Option<&Pat>,
+        rest: &[Stmt],
+        scope: &Scope,
+        frame: &Frame,
+    ) -> syn::Result<proc_macro2::TokenStream> {
+        if Effects::of_expr(e).unsupported {
+            return Err(Error::new_spanned(
+                e,
+                "#[transactional] does not support labeled `break`s and `continue`s, \
+                 and `break`s with values, across `?`",
+            ));
+        }
+        let muts = scope.muts();
+        let mut e = e.clone();
+        Leaf {
+            ctx: self.ctx_ident.clone(),
+            muts: Some(muts.clone()),
+            loops: 0,
+        }.visit_expr_mut(&mut e);
+        let ty = match pat {
+            Some(Pat::Type(p)) => {
+                let ty = &p.ty;
+                quote!(: #ty)
+            }
+            _ => quote!(),
+        };
+        let value = Rewrite::ident("__value");
+        let body = quote! {
+            let #value #ty = #e;
+            ::std::result::Result::Ok(((#(#muts,)*), ::transaction::__private::Flow::Next(#value)))
+        };
+        let Rewrite { ref ctx, ref err_ty, ref item_ty, ref ctx_ident, .. } = *self;
+        let head = quote! {
+            |#ctx_ident: &mut #ctx| -> ::std::result::Result<
+                (_, ::transaction::__private::Flow<_, #item_ty>),
+                #err_ty,
+            >
+        };
+        let f = self.closure(head, body, scope, &[]);
+        let cont = self.cont(pat, rest, scope, frame, &muts)?;
+        Ok(quote!(::transaction::Transaction::and_then(::transaction::with_ctx(#f), #cont)))
+    }
+}
+
+fn transactional_fn(ctx: Type, mut item: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let (item_ty, err_ty) = result_type(&item.sig.output)?;
+
+    let mut scope = Scope::default();
+    let mut named = NameLifetimes(Vec::new());
+    let mut captures = Lifetimes(Vec::new());
+    for arg in item.sig.inputs.iter_mut() {
+        match *arg {
+            FnArg::Receiver(ref r) => {
+                return Err(Error::new_spanned(
+                    r,
+                    "#[transactional] does not support methods",
+                ))
+            }
+            FnArg::Typed(ref mut pat) => {
+                match *pat.pat {
+                    Pat::Ident(ref mut i) if i.by_ref.is_none() && i.subpat.is_none() => {
+                        // `mut` is moved to the binding in the transaction
+                        let mutability = i.mutability.take();
+                        scope.0.push((i.ident.clone(), mutability.is_some()))
+                    }
+                    _ => {
+                        return Err(Error::new_spanned(
+                            &pat.pat,
+                            "#[transactional] arguments must be identifiers",
+                        ))
+                    }
+                }
+                named.visit_type_mut(&mut pat.ty);
+                captures.visit_type(&pat.ty);
+            }
+        }
+    }
+
+    // declare the lifetimes of the context, such as `'a` of `DieselContext<'a, _>`,
+    // and the lifetimes of the arguments
+    let mut lifetimes = Lifetimes(Vec::new());
+    lifetimes.visit_type(&ctx);
+    lifetimes.0.extend(named.0);
+    for lt in lifetimes.0 {
+        let declared = item.sig.generics.lifetimes().any(|l| l.lifetime == lt);
+        if !declared {
+            item.sig
+                .generics
+                .params
+                .insert(0, GenericParam::Lifetime(LifetimeParam::new(lt)));
+        }
+    }
+
+    let mut rewrite = Rewrite {
+        ctx: ctx.clone(),
+        item_ty: item_ty.clone(),
+        err_ty: err_ty.clone(),
+        ctx_ident: Ident::new("__ctx", Span::mixed_site()),
+        iters: 0,
+    };
+    let body = rewrite.block(&item.block.stmts, &scope, &Frame::Body)?;
+    let body = rewrite.closure(quote!(||), body, &scope, &[]);
+
+    let attrs = &item.attrs;
+    let vis = &item.vis;
+    let name = item.sig.ident.to_string();
+    let captures = captures.0;
+    let mut sig = item.sig.clone();
+    sig.output = syn::parse_quote! {
+        -> impl ::transaction::Transaction<Ctx = #ctx, Item = #item_ty, Err = #err_ty>
+            #(+ ::transaction::__private::Captures<#captures>)*
+    };
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            ::transaction::__private::defer(#name, #body)
+        }
+    })
+}
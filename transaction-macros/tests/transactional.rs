@@ -0,0 +1,213 @@
+extern crate transaction;
+
+use transaction::prelude::*;
+use transaction::transactional;
+
+#[derive(Debug, Default)]
+struct Log(Vec<String>);
+
+#[derive(Debug, Clone, PartialEq)]
+struct MyError(String);
+
+impl From<String> for MyError {
+    fn from(s: String) -> Self {
+        MyError(s)
+    }
+}
+
+// push a line to the log and return the number of the lines
+fn push(line: String) -> impl TxFor<Log, usize, String> {
+    with_ctx(move |log: &mut Log| {
+        log.0.push(line.clone());
+        Ok(log.0.len())
+    })
+}
+
+fn fail(msg: &'static str) -> impl TxFor<Log, usize, String> {
+    err(msg.to_string())
+}
+
+fn run<Tx: Transaction<Ctx = Log>>(tx: Tx) -> (Result<Tx::Item, Tx::Err>, Vec<String>) {
+    let mut log = Log::default();
+    let r = tx.run(&mut log);
+    (r, log.0)
+}
+
+#[transactional(ctx = Log)]
+fn sequence(a: String, b: String) -> Result<usize, String> {
+    push(a)?;
+    let n = push(b.clone())?;
+    push(format!("{}{}", b, n))?;
+    Ok(n)
+}
+
+#[test]
+fn sequence_runs_in_order() {
+    let (r, log) = run(sequence("a".into(), "b".into()));
+    assert_eq!(r, Ok(2));
+    assert_eq!(log, vec!["a", "b", "b2"]);
+}
+
+#[test]
+fn run_twice() {
+    let tx = sequence("a".into(), "b".into());
+    assert_eq!(run(&tx), run(&tx));
+}
+
+#[transactional(ctx = Log)]
+fn guard(n: i32) -> Result<i32, String> {
+    push("start".into())?;
+    if n < 0 {
+        return Err("negative".into());
+    }
+    push("positive".into())?;
+    Ok(n)
+}
+
+#[test]
+fn early_return() {
+    assert_eq!(run(guard(1)), (Ok(1), vec!["start".into(), "positive".into()]));
+    assert_eq!(run(guard(-1)), (Err("negative".into()), vec!["start".into()]));
+}
+
+#[transactional(ctx = Log)]
+fn branches(n: i32) -> Result<usize, String> {
+    let m = if n % 2 == 0 {
+        push("even".into())?
+    } else {
+        push("odd".into())?;
+        push("odd".into())?
+    };
+    let label = match n {
+        0 => "zero",
+        1 | 2 => {
+            push("small".into())?;
+            "small"
+        }
+        _ => return Ok(m * 100),
+    };
+    push(label.into())?;
+    Ok(m)
+}
+
+#[test]
+fn if_and_match() {
+    assert_eq!(run(branches(0)), (Ok(1), vec!["even".into(), "zero".into()]));
+    assert_eq!(
+        run(branches(1)),
+        (Ok(2), vec!["odd".into(), "odd".into(), "small".into(), "small".into()])
+    );
+    assert_eq!(run(branches(4)), (Ok(100), vec!["even".into()]));
+}
+
+#[transactional(ctx = Log)]
+fn loops(n: usize) -> Result<usize, String> {
+    let mut sum = 0;
+    for i in 0..n {
+        if i == 1 {
+            continue;
+        }
+        if i == 4 {
+            break;
+        }
+        sum += push(i.to_string())?;
+    }
+    let mut count = 0;
+    while count < 2 {
+        push("while".into())?;
+        count += 1;
+    }
+    loop {
+        if push("loop".into())? > 100 {
+            return Err("too long".into());
+        }
+        if sum > 0 {
+            break;
+        }
+        sum += 1;
+    }
+    Ok(sum + count)
+}
+
+#[test]
+fn loops_thread_mutable_variables() {
+    let (r, log) = run(loops(10));
+    // 1 + 2 + 3 for "0", "2" and "3", 2 for the `while`
+    assert_eq!(r, Ok(8));
+    assert_eq!(log, vec!["0", "2", "3", "while", "while", "loop"]);
+    let (r, log) = run(loops(0));
+    assert_eq!(r, Ok(3));
+    assert_eq!(log, vec!["while", "while", "loop", "loop"]);
+}
+
+#[transactional(ctx = Log)]
+fn results(s: String) -> Result<i32, MyError> {
+    let n: i32 = s.parse().map_err(|_| "not a number".to_string())?;
+    push(s)?;
+    Ok(n)
+}
+
+#[test]
+fn result_and_error_conversion() {
+    assert_eq!(run(results("3".into())), (Ok(3), vec!["3".into()]));
+    assert_eq!(
+        run(results("x".into())),
+        (Err(MyError("not a number".into())), vec![])
+    );
+}
+
+#[transactional(ctx = Log)]
+fn failing() -> Result<usize, String> {
+    push("before".into())?;
+    fail("failed")?;
+    Ok(push("after".into())?)
+}
+
+#[test]
+fn error_stops_the_rest() {
+    assert_eq!(run(failing()), (Err("failed".into()), vec!["before".into()]));
+}
+
+#[transactional(ctx = Log)]
+fn nested(a: String) -> Result<usize, String> {
+    let n = push(a.clone())? + push(a)?;
+    Ok(n + push("last".into())?)
+}
+
+#[test]
+fn nested_question_marks() {
+    assert_eq!(run(nested("a".into())), (Ok(6), vec!["a".into(), "a".into(), "last".into()]));
+}
+
+#[transactional(ctx = Log)]
+fn borrowed(name: &str, names: &[&str]) -> Result<usize, String> {
+    push(name.to_string())?;
+    for n in names.iter() {
+        push(n.to_string())?;
+    }
+    Ok(name.len())
+}
+
+#[test]
+fn reference_arguments() {
+    let name = String::from("name");
+    let (r, log) = run(borrowed(&name, &["x", "y"]));
+    assert_eq!(r, Ok(4));
+    assert_eq!(log, vec!["name", "x", "y"]);
+}
+
+#[test]
+fn describe() {
+    assert_eq!(sequence("a".into(), "b".into()).describe().to_string(), "sequence\n  <fn>\n");
+    // the steps are built at each run, so loops and branches are not shown
+    assert_eq!(loops(3).describe().to_string(), "loops\n  <fn>\n");
+    assert_eq!(branches(1).describe().to_string(), "branches\n  <fn>\n");
+}
+
+#[test]
+fn ready_results_run_more_than_once() {
+    let tx = transaction::__private::ready::<Log, _, String>(Ok(1));
+    assert_eq!(run(&tx), run(&tx));
+    let tx = branches(4);
+    assert_eq!(run(&tx), run(&tx));
+}
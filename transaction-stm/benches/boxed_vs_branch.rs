@@ -9,7 +9,7 @@ use transaction::prelude::*;
 use transaction_stm::{run, with_tx};
 use test::Bencher;
 
-type BoxTx<'a, T> = Box<Transaction<Ctx = stm::Transaction, Item = T, Err = stm::StmError> + 'a>;
+type BoxTx<'a, T> = transaction::BoxTx<'a, stm::Transaction, T, stm::StmError>;
 
 fn inc<'a>(x: &'a stm::TVar<usize>) -> BoxTx<'a, usize> {
     with_tx(move |ctx| {
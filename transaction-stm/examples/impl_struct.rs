@@ -3,40 +3,28 @@ extern crate transaction;
 extern crate transaction_stm;
 
 use transaction::prelude::*;
-use transaction_stm::{run, with_tx};
+use transaction_stm::{modify, read, run};
 
 struct Data {
     x: stm::TVar<i32>,
     y: stm::TVar<i32>,
 }
 
-type BoxTx<'a, T> = Box<Transaction<Ctx = stm::Transaction, Item = T, Err = stm::StmError> + 'a>;
-
 impl Data {
-    fn inc_x(&self) -> BoxTx<i32> {
-        with_tx(move |ctx| {
-            let xv = ctx.read(&self.x)?;
-            ctx.write(&self.x, xv + 1)?;
-            Ok(xv)
-        }).boxed()
+    fn inc_x(&self) -> impl TxFor<stm::Transaction, (), stm::StmError> + '_ {
+        modify(&self.x, |xv| xv + 1)
     }
-    fn inc_y(&self) -> BoxTx<i32> {
-        with_tx(move |ctx| {
-            let yv = ctx.read(&self.y)?;
-            ctx.write(&self.y, yv + 1)?;
-            Ok(yv)
-        }).boxed()
+    fn inc_y(&self) -> impl TxFor<stm::Transaction, (), stm::StmError> + '_ {
+        modify(&self.y, |yv| yv + 1)
     }
 
-    fn inc_xy(&self) -> BoxTx<i32> {
-        self.inc_x().and_then(move |_| self.inc_y()).boxed()
+    fn inc_xy(&self) -> impl TxFor<stm::Transaction, (), stm::StmError> + '_ {
+        self.inc_x().and_then(move |_| self.inc_y())
     }
-    fn add(&self) -> BoxTx<i32> {
-        with_tx(move |ctx| {
-            let xv = ctx.read(&self.x)?;
-            let yv = ctx.read(&self.y)?;
-            Ok(xv + yv)
-        }).boxed()
+    fn add(&self) -> impl TxFor<stm::Transaction, i32, stm::StmError> + '_ {
+        read(&self.x)
+            .join(read(&self.y))
+            .map(|(xv, yv)| xv + yv)
     }
 }
 
@@ -0,0 +1,36 @@
+extern crate stm;
+extern crate transaction;
+extern crate transaction_stm;
+
+use std::thread;
+
+use transaction_stm::{run, TQueue, TSemaphore};
+
+fn main() {
+    let queue = TQueue::new(4);
+    let done = TSemaphore::new(0);
+
+    let producer = {
+        let queue = queue.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            for i in 0..10 {
+                // blocks while the queue is full
+                run(&queue.push(i));
+            }
+            run(&done.release());
+        })
+    };
+
+    let mut sum = 0;
+    for _ in 0..10 {
+        // blocks while the queue is empty
+        sum += run(&queue.pop());
+    }
+    // wait for the producer
+    run(&done.acquire());
+    producer.join().unwrap();
+
+    assert_eq!(sum, 45);
+    assert_eq!(run(&queue.len()), 0);
+}
@@ -37,13 +37,32 @@
 //! }
 //! ```
 
-
+// struct literals spell out the field names, as in `f: f`
+#![allow(clippy::redundant_field_names)]
 
 extern crate stm;
 extern crate transaction;
 
-use transaction::Transaction;
-use stm::Transaction as Stm;
+mod linked;
+mod tchannel;
+mod tmap;
+mod tqueue;
+mod tsemaphore;
+mod tvar;
+
+pub use tchannel::*;
+pub use tmap::*;
+pub use tqueue::*;
+pub use tsemaphore::*;
+pub use tvar::*;
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+use transaction::{AnyTxError, IntoTransaction, Plan, Transaction};
+use stm::{StmError, Transaction as Stm};
 
 
 /// Run the `stm` transaction
@@ -54,6 +73,128 @@ where
     Stm::with(|stm| tx.run(stm))
 }
 
+/// The error type of transactions run by `run_result`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StmTxError<E> {
+    /// The control of `stm`. `StmError::Retry` blocks until one of the read
+    /// `TVar`s changes and `StmError::Failure` restarts the transaction.
+    Stm(StmError),
+    /// Abort the transaction with an error. Nothing is written.
+    Abort(E),
+}
+
+impl<E> From<StmError> for StmTxError<E> {
+    fn from(e: StmError) -> Self {
+        StmTxError::Stm(e)
+    }
+}
+
+impl<E> From<StmTxError<E>> for AnyTxError
+where
+    E: Into<AnyTxError>,
+{
+    fn from(e: StmTxError<E>) -> Self {
+        match e {
+            StmTxError::Stm(e) => StmControlError(e).into(),
+            StmTxError::Abort(e) => e.into(),
+        }
+    }
+}
+
+/// The control of `stm` in `AnyTxError`. Transactions run by `run_any`
+/// return it to retry, e.g. `stm.read(&x).map_err(StmControlError)?`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StmControlError(pub StmError);
+
+impl fmt::Display for StmControlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            StmError::Failure => write!(f, "stm transaction conflicted"),
+            StmError::Retry => write!(f, "stm transaction retried"),
+        }
+    }
+}
+
+impl Error for StmControlError {}
+
+/// Errors that can carry the control of `stm`, such as `retry`.
+pub trait StmControl: From<StmError> + Sized {
+    /// Take out the control of `stm`, or give back the error if it is not.
+    fn into_control(self) -> Result<StmError, Self>;
+}
+
+impl StmControl for StmError {
+    fn into_control(self) -> Result<StmError, Self> {
+        Ok(self)
+    }
+}
+
+impl<E> StmControl for StmTxError<E> {
+    fn into_control(self) -> Result<StmError, Self> {
+        match self {
+            StmTxError::Stm(e) => Ok(e),
+            e => Err(e),
+        }
+    }
+}
+
+/// Run the `stm` transaction that may abort with a user error.
+/// When the transaction returns `StmTxError::Abort`, all the writes are
+/// discarded and the error is returned.
+pub fn run_result<T, E, Tx>(tx: &Tx) -> Result<T, E>
+where
+    Tx: Transaction<Ctx = Stm, Item = T, Err = StmTxError<E>>,
+{
+    run_aborting(tx, |e| match e {
+        StmTxError::Stm(e) => Ok(e),
+        StmTxError::Abort(e) => Err(e),
+    })
+}
+
+/// Run the `stm` transaction whose errors are composed into `AnyTxError`,
+/// e.g. by `context`. `StmControlError` is passed to `stm` and the other
+/// errors abort the transaction as `run_result` does.
+pub fn run_any<T, Tx>(tx: &Tx) -> Result<T, AnyTxError>
+where
+    Tx: Transaction<Ctx = Stm, Item = T, Err = AnyTxError>,
+{
+    run_aborting(tx, |e| match e.downcast_ref::<StmControlError>() {
+        Some(c) => Ok(c.0),
+        None => Err(e),
+    })
+}
+
+// `control` takes out the control of `stm` from the error or gives back the
+// error to abort with
+fn run_aborting<T, E, Tx, F>(tx: &Tx, control: F) -> Result<T, E>
+where
+    Tx: Transaction<Ctx = Stm, Item = T>,
+    F: Fn(Tx::Err) -> Result<StmError, E>,
+{
+    let aborted = RefCell::new(None);
+    Stm::with(|stm| {
+        // Running the transaction as the first alternative of `or` lets us
+        // drop its writes by "retrying" it and then report the error from the
+        // second alternative.
+        stm.or(
+            |stm| match tx.run(stm) {
+                Ok(t) => Ok(Ok(t)),
+                Err(e) => match control(e) {
+                    Ok(c) => Err(c),
+                    Err(e) => {
+                        *aborted.borrow_mut() = Some(e);
+                        Err(StmError::Retry)
+                    }
+                },
+            },
+            |_| match aborted.borrow_mut().take() {
+                Some(e) => Ok(Err(e)),
+                None => Err(StmError::Retry),
+            },
+        )
+    })
+}
+
 pub fn with_tx<F, T, E>(f: F) -> WithTx<F>
 where
     F: Fn(&mut Stm) -> Result<T, E>,
@@ -77,3 +218,94 @@ where
         f(ctx)
     }
 }
+
+/// Block the transaction until one of the read `TVar`s changes.
+pub fn stm_retry<T, E>() -> StmRetry<T, E>
+where
+    E: From<StmError>,
+{
+    StmRetry { _phantom: PhantomData }
+}
+
+/// The result of `stm_retry`
+#[derive(Debug)]
+#[must_use]
+pub struct StmRetry<T, E> {
+    _phantom: PhantomData<fn() -> (T, E)>,
+}
+
+impl<T, E> Transaction for StmRetry<T, E>
+where
+    E: From<StmError>,
+{
+    type Ctx = Stm;
+    type Item = T;
+    type Err = E;
+    fn run(&self, _ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        Err(StmError::Retry.into())
+    }
+}
+
+/// Run `a`, and if it calls `stm_retry`, discard its writes and run `b` instead.
+/// This corresponds to `stm::Transaction::or`.
+pub fn or_alt<A, B>(a: A, b: B) -> OrAlt<A::Tx, B::Tx>
+where
+    A: IntoTransaction<Stm>,
+    B: IntoTransaction<Stm, Item = A::Item, Err = A::Err>,
+    A::Err: StmControl,
+{
+    OrAlt {
+        tx1: a.into_transaction(),
+        tx2: b.into_transaction(),
+    }
+}
+
+/// The result of `or_alt`
+#[derive(Debug)]
+#[must_use]
+pub struct OrAlt<Tx1, Tx2> {
+    tx1: Tx1,
+    tx2: Tx2,
+}
+
+impl<Tx1, Tx2> Transaction for OrAlt<Tx1, Tx2>
+where
+    Tx1: Transaction<Ctx = Stm>,
+    Tx2: Transaction<Ctx = Stm, Item = Tx1::Item, Err = Tx1::Err>,
+    Tx1::Err: StmControl,
+{
+    type Ctx = Stm;
+    type Item = Tx1::Item;
+    type Err = Tx1::Err;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let OrAlt { ref tx1, ref tx2 } = *self;
+        match ctx.or(
+            |stm| split_control(tx1.run(stm)),
+            |stm| split_control(tx2.run(stm)),
+        ) {
+            Ok(r) => r,
+            Err(c) => Err(c.into()),
+        }
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::node("or_alt", vec![self.tx1.describe(), self.tx2.describe()])
+    }
+}
+
+// Only the control of stm is passed to `stm`; the other errors are returned as
+// successful values so that they are not consumed by `or`.
+fn split_control<T, E>(r: Result<T, E>) -> stm::StmResult<Result<T, E>>
+where
+    E: StmControl,
+{
+    match r {
+        Ok(t) => Ok(Ok(t)),
+        Err(e) => {
+            match e.into_control() {
+                Ok(c) => Err(c),
+                Err(e) => Ok(Err(e)),
+            }
+        }
+    }
+}
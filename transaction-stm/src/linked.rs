@@ -0,0 +1,52 @@
+use std::any::Any;
+
+use stm::{self, StmResult, TVar};
+
+// a cell of the list. The last one is always `Nil`, the hole filled by the
+// next push.
+#[derive(Clone)]
+enum Node<T> {
+    Nil,
+    Cons(T, TVar<Node<T>>),
+}
+
+/// A FIFO list of `TVar`s linked from the front to the back, so that a push
+/// or a pop copies one item instead of the whole list. Clones share the list.
+#[derive(Clone)]
+pub(crate) struct Linked<T> {
+    // the first cell
+    front: TVar<TVar<Node<T>>>,
+    // the hole at the end
+    back: TVar<TVar<Node<T>>>,
+}
+
+impl<T> Linked<T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    pub(crate) fn new() -> Self {
+        let hole = TVar::new(Node::Nil);
+        Linked {
+            front: TVar::new(hole.clone()),
+            back: TVar::new(hole),
+        }
+    }
+
+    pub(crate) fn push_back(&self, ctx: &mut stm::Transaction, value: T) -> StmResult<()> {
+        let hole = ctx.read(&self.back)?;
+        let next = TVar::new(Node::Nil);
+        ctx.write(&hole, Node::Cons(value, next.clone()))?;
+        ctx.write(&self.back, next)
+    }
+
+    pub(crate) fn pop_front(&self, ctx: &mut stm::Transaction) -> StmResult<Option<T>> {
+        let first = ctx.read(&self.front)?;
+        match ctx.read(&first)? {
+            Node::Nil => Ok(None),
+            Node::Cons(value, next) => {
+                ctx.write(&self.front, next)?;
+                Ok(Some(value))
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::any::Any;
+
+use stm::{self, StmError};
+use transaction::Transaction;
+
+use linked::Linked;
+
+/// An unbounded multi-producer multi-consumer channel. Clones share the same
+/// channel. `send` never blocks and `recv` blocks while it is empty.
+#[derive(Clone)]
+pub struct TChannel<T> {
+    items: Linked<T>,
+}
+
+impl<T> Default for TChannel<T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    fn default() -> Self {
+        TChannel::new()
+    }
+}
+
+impl<T> TChannel<T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    /// Create an empty channel.
+    pub fn new() -> Self {
+        TChannel { items: Linked::new() }
+    }
+
+    /// Send a value to the channel.
+    pub fn send(&self, value: T) -> ChannelSend<'_, T> {
+        ChannelSend {
+            channel: self,
+            value: value,
+        }
+    }
+
+    /// Receive a value from the channel, blocking while it is empty.
+    pub fn recv(&self) -> ChannelRecv<'_, T> {
+        ChannelRecv { channel: self }
+    }
+}
+
+/// The result of `TChannel::send`
+#[must_use]
+pub struct ChannelSend<'a, T: 'a> {
+    channel: &'a TChannel<T>,
+    value: T,
+}
+
+impl<'a, T> Transaction for ChannelSend<'a, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    type Ctx = stm::Transaction;
+    type Item = ();
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let ChannelSend { channel, ref value } = *self;
+        channel.items.push_back(ctx, value.clone())
+    }
+}
+
+/// The result of `TChannel::recv`
+#[must_use]
+pub struct ChannelRecv<'a, T: 'a> {
+    channel: &'a TChannel<T>,
+}
+
+impl<'a, T> Transaction for ChannelRecv<'a, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    type Ctx = stm::Transaction;
+    type Item = T;
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        match self.channel.items.pop_front(ctx)? {
+            Some(v) => Ok(v),
+            None => Err(StmError::Retry),
+        }
+    }
+}
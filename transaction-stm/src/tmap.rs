@@ -0,0 +1,136 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use stm::{self, StmError, TVar};
+use transaction::Transaction;
+
+/// A transactional hash map. Note that the whole map is a single `TVar`, so
+/// concurrent writes to different keys conflict with each other, and each
+/// operation copies the map. Clones share the same map.
+#[derive(Clone)]
+pub struct TMap<K, V> {
+    map: TVar<HashMap<K, V>>,
+}
+
+impl<K, V> Default for TMap<K, V>
+where
+    K: Any + Send + Sync + Clone + Hash + Eq,
+    V: Any + Send + Sync + Clone,
+{
+    fn default() -> Self {
+        TMap::new()
+    }
+}
+
+impl<K, V> TMap<K, V>
+where
+    K: Any + Send + Sync + Clone + Hash + Eq,
+    V: Any + Send + Sync + Clone,
+{
+    /// Create an empty map.
+    pub fn new() -> Self {
+        TMap { map: TVar::new(HashMap::new()) }
+    }
+
+    /// Get the value of the key.
+    pub fn get(&self, key: K) -> MapGet<'_, K, V> {
+        MapGet {
+            map: self,
+            key: key,
+        }
+    }
+
+    /// Insert the value to the key and return the previous one.
+    pub fn insert(&self, key: K, value: V) -> MapInsert<'_, K, V> {
+        MapInsert {
+            map: self,
+            key: key,
+            value: value,
+        }
+    }
+
+    /// Remove the key and return its value.
+    pub fn remove(&self, key: K) -> MapRemove<'_, K, V> {
+        MapRemove {
+            map: self,
+            key: key,
+        }
+    }
+}
+
+/// The result of `TMap::get`
+#[must_use]
+pub struct MapGet<'a, K: 'a, V: 'a> {
+    map: &'a TMap<K, V>,
+    key: K,
+}
+
+impl<'a, K, V> Transaction for MapGet<'a, K, V>
+where
+    K: Any + Send + Sync + Clone + Hash + Eq,
+    V: Any + Send + Sync + Clone,
+{
+    type Ctx = stm::Transaction;
+    type Item = Option<V>;
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let MapGet { map, ref key } = *self;
+        Ok(ctx.read(&map.map)?.get(key).cloned())
+    }
+}
+
+/// The result of `TMap::insert`
+#[must_use]
+pub struct MapInsert<'a, K: 'a, V: 'a> {
+    map: &'a TMap<K, V>,
+    key: K,
+    value: V,
+}
+
+impl<'a, K, V> Transaction for MapInsert<'a, K, V>
+where
+    K: Any + Send + Sync + Clone + Hash + Eq,
+    V: Any + Send + Sync + Clone,
+{
+    type Ctx = stm::Transaction;
+    type Item = Option<V>;
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let MapInsert {
+            map,
+            ref key,
+            ref value,
+        } = *self;
+        let mut m = ctx.read(&map.map)?;
+        let prev = m.insert(key.clone(), value.clone());
+        ctx.write(&map.map, m)?;
+        Ok(prev)
+    }
+}
+
+/// The result of `TMap::remove`
+#[must_use]
+pub struct MapRemove<'a, K: 'a, V: 'a> {
+    map: &'a TMap<K, V>,
+    key: K,
+}
+
+impl<'a, K, V> Transaction for MapRemove<'a, K, V>
+where
+    K: Any + Send + Sync + Clone + Hash + Eq,
+    V: Any + Send + Sync + Clone,
+{
+    type Ctx = stm::Transaction;
+    type Item = Option<V>;
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let MapRemove { map, ref key } = *self;
+        let mut m = ctx.read(&map.map)?;
+        let prev = m.remove(key);
+        if prev.is_some() {
+            ctx.write(&map.map, m)?;
+        }
+        Ok(prev)
+    }
+}
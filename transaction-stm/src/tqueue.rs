@@ -0,0 +1,151 @@
+use std::any::Any;
+
+use stm::{self, StmError, TVar};
+use transaction::Transaction;
+
+use linked::Linked;
+
+/// A bounded FIFO queue. `push` blocks while it is full and `pop` blocks
+/// while it is empty. Clones share the same queue.
+#[derive(Clone)]
+pub struct TQueue<T> {
+    items: Linked<T>,
+    len: TVar<usize>,
+    capacity: usize,
+}
+
+impl<T> TQueue<T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    /// Create an empty queue that holds at most `capacity` items.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0, as every `push` would block forever.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "the capacity of TQueue must be positive");
+        TQueue {
+            items: Linked::new(),
+            len: TVar::new(0),
+            capacity: capacity,
+        }
+    }
+
+    /// Push a value to the back of the queue, blocking while it is full.
+    pub fn push(&self, value: T) -> QueuePush<'_, T> {
+        QueuePush {
+            queue: self,
+            value: value,
+        }
+    }
+
+    /// Pop a value from the front of the queue, blocking while it is empty.
+    pub fn pop(&self) -> QueuePop<'_, T> {
+        QueuePop { queue: self }
+    }
+
+    /// Pop a value from the front of the queue if any.
+    pub fn try_pop(&self) -> QueueTryPop<'_, T> {
+        QueueTryPop { queue: self }
+    }
+
+    /// The number of items in the queue.
+    pub fn len(&self) -> QueueLen<'_, T> {
+        QueueLen { queue: self }
+    }
+}
+
+/// The result of `TQueue::push`
+#[must_use]
+pub struct QueuePush<'a, T: 'a> {
+    queue: &'a TQueue<T>,
+    value: T,
+}
+
+impl<'a, T> Transaction for QueuePush<'a, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    type Ctx = stm::Transaction;
+    type Item = ();
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let QueuePush { queue, ref value } = *self;
+        let len = ctx.read(&queue.len)?;
+        if queue.capacity <= len {
+            return Err(StmError::Retry);
+        }
+        queue.items.push_back(ctx, value.clone())?;
+        ctx.write(&queue.len, len + 1)
+    }
+}
+
+/// The result of `TQueue::pop`
+#[must_use]
+pub struct QueuePop<'a, T: 'a> {
+    queue: &'a TQueue<T>,
+}
+
+impl<'a, T> Transaction for QueuePop<'a, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    type Ctx = stm::Transaction;
+    type Item = T;
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        match pop_front(ctx, self.queue)? {
+            Some(v) => Ok(v),
+            None => Err(StmError::Retry),
+        }
+    }
+}
+
+/// The result of `TQueue::try_pop`
+#[must_use]
+pub struct QueueTryPop<'a, T: 'a> {
+    queue: &'a TQueue<T>,
+}
+
+impl<'a, T> Transaction for QueueTryPop<'a, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    type Ctx = stm::Transaction;
+    type Item = Option<T>;
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        pop_front(ctx, self.queue)
+    }
+}
+
+fn pop_front<T>(ctx: &mut stm::Transaction, queue: &TQueue<T>) -> stm::StmResult<Option<T>>
+where
+    T: Any + Send + Sync + Clone,
+{
+    let v = queue.items.pop_front(ctx)?;
+    if v.is_some() {
+        let len = ctx.read(&queue.len)?;
+        ctx.write(&queue.len, len - 1)?;
+    }
+    Ok(v)
+}
+
+/// The result of `TQueue::len`
+#[must_use]
+pub struct QueueLen<'a, T: 'a> {
+    queue: &'a TQueue<T>,
+}
+
+impl<'a, T> Transaction for QueueLen<'a, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    type Ctx = stm::Transaction;
+    type Item = usize;
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.read(&self.queue.len)
+    }
+}
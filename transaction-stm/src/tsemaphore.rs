@@ -0,0 +1,81 @@
+use stm::{self, StmError, TVar};
+use transaction::Transaction;
+
+/// A counting semaphore. `acquire` blocks while no permits are available.
+#[derive(Clone)]
+pub struct TSemaphore {
+    permits: TVar<usize>,
+}
+
+impl TSemaphore {
+    /// Create a semaphore with `permits` permits.
+    pub fn new(permits: usize) -> Self {
+        TSemaphore { permits: TVar::new(permits) }
+    }
+
+    /// Take a permit, blocking while no permits are available.
+    pub fn acquire(&self) -> SemaphoreAcquire<'_> {
+        SemaphoreAcquire { semaphore: self }
+    }
+
+    /// Give back a permit.
+    pub fn release(&self) -> SemaphoreRelease<'_> {
+        SemaphoreRelease { semaphore: self }
+    }
+
+    /// The number of available permits.
+    pub fn available(&self) -> SemaphoreAvailable<'_> {
+        SemaphoreAvailable { semaphore: self }
+    }
+}
+
+/// The result of `TSemaphore::acquire`
+#[must_use]
+pub struct SemaphoreAcquire<'a> {
+    semaphore: &'a TSemaphore,
+}
+
+impl<'a> Transaction for SemaphoreAcquire<'a> {
+    type Ctx = stm::Transaction;
+    type Item = ();
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let permits = &self.semaphore.permits;
+        match ctx.read(permits)? {
+            0 => Err(StmError::Retry),
+            n => ctx.write(permits, n - 1),
+        }
+    }
+}
+
+/// The result of `TSemaphore::release`
+#[must_use]
+pub struct SemaphoreRelease<'a> {
+    semaphore: &'a TSemaphore,
+}
+
+impl<'a> Transaction for SemaphoreRelease<'a> {
+    type Ctx = stm::Transaction;
+    type Item = ();
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let permits = &self.semaphore.permits;
+        let n = ctx.read(permits)?;
+        ctx.write(permits, n + 1)
+    }
+}
+
+/// The result of `TSemaphore::available`
+#[must_use]
+pub struct SemaphoreAvailable<'a> {
+    semaphore: &'a TSemaphore,
+}
+
+impl<'a> Transaction for SemaphoreAvailable<'a> {
+    type Ctx = stm::Transaction;
+    type Item = usize;
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.read(&self.semaphore.permits)
+    }
+}
@@ -0,0 +1,156 @@
+use std::any::Any;
+
+use stm::{self, StmError, TVar};
+use transaction::Transaction;
+
+/// Read the value of the `TVar`.
+pub fn read<T>(var: &TVar<T>) -> Read<'_, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    Read { var: var }
+}
+
+/// The result of `read`
+#[must_use]
+pub struct Read<'a, T: 'a> {
+    var: &'a TVar<T>,
+}
+
+impl<'a, T> Transaction for Read<'a, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    type Ctx = stm::Transaction;
+    type Item = T;
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.read(self.var)
+    }
+}
+
+/// Write the value to the `TVar`.
+pub fn write<T>(var: &TVar<T>, value: T) -> Write<'_, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    Write {
+        var: var,
+        value: value,
+    }
+}
+
+/// The result of `write`
+#[must_use]
+pub struct Write<'a, T: 'a> {
+    var: &'a TVar<T>,
+    value: T,
+}
+
+impl<'a, T> Transaction for Write<'a, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    type Ctx = stm::Transaction;
+    type Item = ();
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let Write { var, ref value } = *self;
+        ctx.write(var, value.clone())
+    }
+}
+
+/// Update the value of the `TVar` with the function.
+pub fn modify<T, F>(var: &TVar<T>, f: F) -> Modify<'_, T, F>
+where
+    T: Any + Send + Sync + Clone,
+    F: Fn(T) -> T,
+{
+    Modify { var: var, f: f }
+}
+
+/// The result of `modify`
+#[must_use]
+pub struct Modify<'a, T: 'a, F> {
+    var: &'a TVar<T>,
+    f: F,
+}
+
+impl<'a, T, F> Transaction for Modify<'a, T, F>
+where
+    T: Any + Send + Sync + Clone,
+    F: Fn(T) -> T,
+{
+    type Ctx = stm::Transaction;
+    type Item = ();
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let Modify { var, ref f } = *self;
+        let v = ctx.read(var)?;
+        ctx.write(var, f(v))
+    }
+}
+
+/// Write the value to the `TVar` and return the previous one.
+pub fn replace<T>(var: &TVar<T>, value: T) -> Replace<'_, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    Replace {
+        var: var,
+        value: value,
+    }
+}
+
+/// The result of `replace`
+#[must_use]
+pub struct Replace<'a, T: 'a> {
+    var: &'a TVar<T>,
+    value: T,
+}
+
+impl<'a, T> Transaction for Replace<'a, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    type Ctx = stm::Transaction;
+    type Item = T;
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let Replace { var, ref value } = *self;
+        let prev = ctx.read(var)?;
+        ctx.write(var, value.clone())?;
+        Ok(prev)
+    }
+}
+
+/// Swap the values of the two `TVar`s.
+pub fn swap<'a, T>(a: &'a TVar<T>, b: &'a TVar<T>) -> Swap<'a, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    Swap { a: a, b: b }
+}
+
+/// The result of `swap`
+#[must_use]
+pub struct Swap<'a, T: 'a> {
+    a: &'a TVar<T>,
+    b: &'a TVar<T>,
+}
+
+impl<'a, T> Transaction for Swap<'a, T>
+where
+    T: Any + Send + Sync + Clone,
+{
+    type Ctx = stm::Transaction;
+    type Item = ();
+    type Err = StmError;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let Swap { a, b } = *self;
+        let av = ctx.read(a)?;
+        let bv = ctx.read(b)?;
+        ctx.write(a, bv)?;
+        ctx.write(b, av)
+    }
+}
@@ -0,0 +1,164 @@
+extern crate stm;
+extern crate transaction;
+extern crate transaction_stm;
+
+mod common;
+
+use std::error;
+use std::fmt;
+
+use stm::{StmError, TVar};
+use transaction::Transaction;
+use transaction_stm::{or_alt, run_any, run_result, stm_retry, with_tx, StmControlError, StmTxError};
+
+use common::assert_blocks;
+
+type Error = StmTxError<&'static str>;
+
+fn write(var: &TVar<i32>, v: i32) -> impl Transaction<Ctx = stm::Transaction, Item = (), Err = Error> + '_ {
+    with_tx(move |stm| Ok(stm.write(var, v)?))
+}
+
+fn abort<T>() -> impl Transaction<Ctx = stm::Transaction, Item = T, Err = Error> {
+    with_tx(|_| Err(StmTxError::Abort("aborted")))
+}
+
+// run `f` in another thread and check that it blocks until `x` is set to 1
+fn assert_blocks_until_set<F>(x: &TVar<i32>, f: F) -> i32
+where
+    F: FnOnce() -> i32 + Send + 'static,
+{
+    assert_blocks(f, || stm::atomically(|stm| x.write(stm, 1)))
+}
+
+#[test]
+fn run_result_commits() {
+    let x = TVar::new(0);
+    assert_eq!(run_result(&write(&x, 1)), Ok(()));
+    assert_eq!(x.read_atomic(), 1);
+}
+
+#[test]
+fn abort_discards_writes() {
+    let x = TVar::new(0);
+    let tx = write(&x, 1).and_then(|_| abort::<()>());
+    assert_eq!(run_result(&tx), Err("aborted"));
+    assert_eq!(x.read_atomic(), 0);
+}
+
+#[test]
+fn retry_blocks_in_run_result() {
+    let x = TVar::new(0);
+    let y = x.clone();
+    let ret = assert_blocks_until_set(&x, move || {
+        let tx = with_tx(|stm| match stm.read(&y)? {
+            0 => Err(StmError::Retry.into()),
+            v => Ok(v),
+        });
+        run_result::<_, &str, _>(&tx).unwrap()
+    });
+    assert_eq!(ret, 1);
+}
+
+#[test]
+fn or_alt_runs_second_on_retry() {
+    let x = TVar::new(0);
+    let y = TVar::new(0);
+    let tx = or_alt(
+        write(&x, 1).and_then(|_| stm_retry()),
+        write(&y, 1),
+    );
+    assert_eq!(run_result(&tx), Ok(()));
+    // the writes of the retried alternative are discarded
+    assert_eq!(x.read_atomic(), 0);
+    assert_eq!(y.read_atomic(), 1);
+}
+
+#[test]
+fn or_alt_does_not_catch_abort() {
+    let x = TVar::new(0);
+    let y = TVar::new(0);
+    let tx = or_alt(write(&x, 1).and_then(|_| abort::<()>()), write(&y, 1));
+    assert_eq!(run_result(&tx), Err("aborted"));
+    assert_eq!(x.read_atomic(), 0);
+    assert_eq!(y.read_atomic(), 0);
+}
+
+#[test]
+fn or_alt_blocks_when_both_retry() {
+    let x = TVar::new(0);
+    let y = x.clone();
+    let ret = assert_blocks_until_set(&x, move || {
+        let wait = |v| {
+            let y = y.clone();
+            with_tx(move |stm| if stm.read(&y)? == v {
+                Ok(v)
+            } else {
+                Err(StmError::Retry.into())
+            })
+        };
+        run_result::<_, &str, _>(&or_alt(wait(2), wait(1))).unwrap()
+    });
+    assert_eq!(ret, 1);
+}
+
+#[derive(Debug, PartialEq)]
+struct Insufficient;
+
+impl fmt::Display for Insufficient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "insufficient balance")
+    }
+}
+
+impl error::Error for Insufficient {}
+
+fn withdraw(var: &TVar<i32>, v: i32) -> impl Transaction<Ctx = stm::Transaction, Item = (), Err = StmTxError<Insufficient>> + '_ {
+    with_tx(move |stm| {
+        let balance = stm.read(var)?;
+        stm.write(var, balance - v)?;
+        if balance < v {
+            return Err(StmTxError::Abort(Insufficient));
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn stm_errors_compose_into_any_tx_error() {
+    let x = TVar::new(1);
+    let tx = withdraw(&x, 1).and_then(|_| withdraw(&x, 1)).context("withdraw twice");
+    let e = run_any(&tx).unwrap_err();
+    assert_eq!(e.to_string(), "withdraw twice: insufficient balance");
+    assert_eq!(e.downcast_ref::<Insufficient>(), Some(&Insufficient));
+    assert_eq!(x.read_atomic(), 1);
+
+    assert!(run_any(&withdraw(&x, 1).context("withdraw")).is_ok());
+    assert_eq!(x.read_atomic(), 0);
+}
+
+#[test]
+fn retry_blocks_in_run_any() {
+    let x = TVar::new(0);
+    let y = x.clone();
+    let ret = assert_blocks_until_set(&x, move || {
+        let tx = with_tx(|stm| match stm.read(&y).map_err(StmControlError)? {
+            0 => Err(StmControlError(StmError::Retry).into()),
+            v => Ok(v),
+        });
+        run_any(&tx).unwrap()
+    });
+    assert_eq!(ret, 1);
+
+    // the control is kept through `context`
+    let x = TVar::new(0);
+    let y = x.clone();
+    let ret = assert_blocks_until_set(&x, move || {
+        let tx = with_tx(|stm| match stm.read(&y)? {
+            0 => Err(StmTxError::<Insufficient>::Stm(StmError::Retry)),
+            v => Ok(v),
+        }).context("wait");
+        run_any(&tx).unwrap()
+    });
+    assert_eq!(ret, 1);
+}
@@ -0,0 +1,21 @@
+#![allow(dead_code)]
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// run `f` in another thread and check that it blocks until `unblock` is run
+pub fn assert_blocks<T, F, G>(f: F, unblock: G) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+    G: FnOnce(),
+{
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || sender.send(f()).unwrap());
+    assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    unblock();
+    let ret = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    handle.join().unwrap();
+    ret
+}
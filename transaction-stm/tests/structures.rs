@@ -0,0 +1,102 @@
+extern crate stm;
+extern crate transaction;
+extern crate transaction_stm;
+
+mod common;
+
+use transaction::Transaction;
+use transaction_stm::{run, run_result, with_tx, StmTxError, TChannel, TMap, TQueue, TSemaphore};
+
+use common::assert_blocks;
+
+#[test]
+fn queue_is_fifo() {
+    let queue = TQueue::new(3);
+    run(&queue.push(1).and_then(|_| queue.push(2)));
+    assert_eq!(run(&queue.len()), 2);
+    assert_eq!(run(&queue.pop()), 1);
+    assert_eq!(run(&queue.try_pop()), Some(2));
+    assert_eq!(run(&queue.try_pop()), None);
+}
+
+#[test]
+fn queue_pop_blocks_while_empty() {
+    let queue = TQueue::new(1);
+    let q = queue.clone();
+    let v = assert_blocks(move || run(&q.pop()), || run(&queue.push(1)));
+    assert_eq!(v, 1);
+}
+
+#[test]
+fn queue_push_blocks_while_full() {
+    let queue = TQueue::new(1);
+    run(&queue.push(1));
+    let q = queue.clone();
+    assert_blocks(move || run(&q.push(2)), || assert_eq!(run(&queue.pop()), 1));
+    assert_eq!(run(&queue.pop()), 2);
+}
+
+#[test]
+fn queue_keeps_the_order_of_many_items() {
+    let queue = TQueue::new(100);
+    for i in 0..100 {
+        run(&queue.push(i));
+    }
+    assert_eq!(run(&queue.len()), 100);
+    for i in 0..100 {
+        assert_eq!(run(&queue.pop()), i);
+    }
+    assert_eq!(run(&queue.len()), 0);
+}
+
+#[test]
+fn queue_discards_aborted_pushes_and_pops() {
+    let queue = TQueue::new(3);
+    run(&queue.push(1));
+    let tx = queue
+        .push(2)
+        .and_then(|_| queue.pop())
+        .map_err(StmTxError::Stm)
+        .and_then(|_| with_tx(|_| Err::<(), _>(StmTxError::Abort("aborted"))));
+    assert_eq!(run_result(&tx), Err("aborted"));
+    assert_eq!(run(&queue.len()), 1);
+    assert_eq!(run(&queue.try_pop()), Some(1));
+    assert_eq!(run(&queue.try_pop()), None);
+}
+
+#[test]
+#[should_panic]
+fn queue_rejects_zero_capacity() {
+    TQueue::<i32>::new(0);
+}
+
+#[test]
+fn map_get_insert_remove() {
+    let map = TMap::new();
+    assert_eq!(run(&map.insert("a", 1)), None);
+    assert_eq!(run(&map.insert("a", 2)), Some(1));
+    assert_eq!(run(&map.get("a")), Some(2));
+    assert_eq!(run(&map.remove("a")), Some(2));
+    assert_eq!(run(&map.get("a")), None);
+}
+
+#[test]
+fn semaphore_acquire_blocks_without_permits() {
+    let semaphore = TSemaphore::new(1);
+    run(&semaphore.acquire());
+    assert_eq!(run(&semaphore.available()), 0);
+    let s = semaphore.clone();
+    assert_blocks(move || run(&s.acquire()), || run(&semaphore.release()));
+    assert_eq!(run(&semaphore.available()), 0);
+}
+
+#[test]
+fn channel_recv_blocks_while_empty() {
+    let channel = TChannel::new();
+    run(&channel.send(1).and_then(|_| channel.send(2)));
+    assert_eq!(run(&channel.recv()), 1);
+    assert_eq!(run(&channel.recv()), 2);
+    let c = channel.clone();
+    let v = assert_blocks(move || run(&c.recv()), || run(&channel.send(3)));
+    assert_eq!(v, 3);
+}
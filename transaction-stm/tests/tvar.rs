@@ -0,0 +1,65 @@
+extern crate stm;
+extern crate transaction;
+extern crate transaction_stm;
+
+use stm::TVar;
+use transaction::Transaction;
+use transaction_stm::{modify, read, replace, run, run_result, swap, with_tx, write, StmTxError};
+
+type Error = StmTxError<&'static str>;
+
+#[test]
+fn read_and_write() {
+    let x = TVar::new(1);
+    assert_eq!(run(&read(&x)), 1);
+    run(&write(&x, 2));
+    assert_eq!(x.read_atomic(), 2);
+    // the write is seen by the later reads in the transaction
+    assert_eq!(run(&write(&x, 3).and_then(|_| read(&x))), 3);
+}
+
+#[test]
+fn modify_applies_the_function() {
+    let x = TVar::new(1);
+    run(&modify(&x, |v| v + 1).and_then(|_| modify(&x, |v| v * 10)));
+    assert_eq!(x.read_atomic(), 20);
+}
+
+#[test]
+fn replace_returns_the_previous_value() {
+    let x = TVar::new(1);
+    assert_eq!(run(&replace(&x, 2)), 1);
+    assert_eq!(run(&replace(&x, 3)), 2);
+    assert_eq!(x.read_atomic(), 3);
+}
+
+#[test]
+fn swap_exchanges_the_values() {
+    let x = TVar::new(1);
+    let y = TVar::new(2);
+    run(&swap(&x, &y));
+    assert_eq!((x.read_atomic(), y.read_atomic()), (2, 1));
+}
+
+#[test]
+fn accessors_compose_in_run_result() {
+    let x = TVar::new(1);
+    let y = TVar::new(10);
+    let tx = read(&x)
+        .and_then(|v| write(&y, v + 10).map(move |_| v))
+        .and_then(|v| replace(&x, v + 1))
+        .and_then(|_| swap(&x, &y))
+        .and_then(|_| modify(&y, |v| v * 2))
+        .err_into::<Error>()
+        .and_then(|_| read(&x).err_into());
+    assert_eq!(run_result(&tx), Ok(11));
+    assert_eq!((x.read_atomic(), y.read_atomic()), (11, 4));
+
+    // aborted writes are discarded
+    let tx = write(&x, 0)
+        .and_then(|_| replace(&y, 0))
+        .err_into::<Error>()
+        .and_then(|_| with_tx(|_| Err::<(), _>(StmTxError::Abort("aborted"))));
+    assert_eq!(run_result(&tx), Err("aborted"));
+    assert_eq!((x.read_atomic(), y.read_atomic()), (11, 4));
+}
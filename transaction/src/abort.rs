@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use {IntoTransaction, Plan, Transaction};
 
 /// Take the previous successfull value of computation and abort the
 /// transaction.
@@ -23,7 +23,7 @@ where
 pub struct Abort<Tx, T, F> {
     tx: Tx,
     f: F,
-    _phantom: PhantomData<T>,
+    _phantom: PhantomData<fn() -> T>,
 }
 
 impl<Tx, F, T> Transaction for Abort<Tx, T, F>
@@ -42,4 +42,8 @@ where
             Err(e) => Err(e),
         }
     }
+
+    fn describe(&self) -> Plan {
+        Plan::node("abort", vec![self.tx.describe()])
+    }
 }
@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use {Batch, IntoTransaction, Plan, Transaction};
 
 pub fn and_then<Ctx, A, F, B>(a: A, f: F) -> AndThen<A::Tx, F, B>
 where
@@ -22,7 +22,7 @@ where
 pub struct AndThen<Tx1, F, Tx2> {
     tx: Tx1,
     f: F,
-    _phantom: PhantomData<Tx2>,
+    _phantom: PhantomData<fn() -> Tx2>,
 }
 
 
@@ -42,4 +42,18 @@ where
             |item| f(item).into_transaction().run(ctx),
         )
     }
+
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        self.tx.prefetch(batch)
+    }
+
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
+        self.tx.run_prefetched(ctx, batch).and_then(
+            |item| (self.f)(item).into_transaction().run(ctx),
+        )
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::node("and_then", vec![self.tx.describe(), Plan::closure()])
+    }
 }
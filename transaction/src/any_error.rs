@@ -0,0 +1,98 @@
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+
+/// A boxed error that can hold errors of any transaction.
+/// Use this to compose transactions whose error types differ (e.g. database
+/// errors, domain errors and stm errors) into one tree.
+/// Contexts are attached with `context` and are printed outermost first.
+pub struct AnyTxError {
+    error: Box<dyn Error + Send + Sync>,
+    context: Vec<Cow<'static, str>>,
+    backtrace: Backtrace,
+}
+
+impl AnyTxError {
+    /// Wrap an error. The backtrace is captured here.
+    pub fn new<E>(e: E) -> Self
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        AnyTxError {
+            error: e.into(),
+            context: Vec::new(),
+            backtrace: Backtrace::capture(),
+        }
+    }
+
+    /// Attach a context describing what was being done when the error occured.
+    pub fn context<C>(mut self, c: C) -> Self
+    where
+        C: Into<Cow<'static, str>>,
+    {
+        self.context.push(c.into());
+        self
+    }
+
+    /// The attached contexts, outermost first
+    pub fn contexts(&self) -> Vec<&str> {
+        self.context.iter().rev().map(|c| c.as_ref()).collect()
+    }
+
+    /// The wrapped error
+    pub fn error(&self) -> &(dyn Error + Send + Sync + 'static) {
+        &*self.error
+    }
+
+    /// The backtrace captured when the error is wrapped
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+
+    /// Get a reference to the wrapped error if it is of type `E`
+    pub fn downcast_ref<E>(&self) -> Option<&E>
+    where
+        E: Error + 'static,
+    {
+        self.error.downcast_ref()
+    }
+
+    /// Unwrap the error dropping the contexts
+    pub fn into_inner(self) -> Box<dyn Error + Send + Sync> {
+        self.error
+    }
+}
+
+impl<E> From<E> for AnyTxError
+where
+    E: Error + Send + Sync + 'static,
+{
+    fn from(e: E) -> Self {
+        AnyTxError::new(e)
+    }
+}
+
+impl fmt::Display for AnyTxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.context.iter().rev() {
+            write!(f, "{}: ", c)?;
+        }
+        write!(f, "{}", self.error)
+    }
+}
+
+impl fmt::Debug for AnyTxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)?;
+        let mut source = self.error.source();
+        while let Some(e) = source {
+            write!(f, "\ncaused by: {}", e)?;
+            source = e.source();
+        }
+        if let BacktraceStatus::Captured = self.backtrace.status() {
+            write!(f, "\n\n{}", self.backtrace)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+//! Generic runners of transactions.
+//!
+//! A backend, such as a database connection, implements `TxBackend` and the
+//! runners in this module take care of beginning, committing and rolling back
+//! transactions. Backends that only run transactions inside a closure, such as
+//! `stm`, cannot implement `TxBackend` and provide their own runners.
+//! The runners also call the hooks registered by `on_commit` and
+//! `on_rollback` for the backends returning them from `TxBackend::hooks_mut`.
+
+use {Hooks, PropagationError, Transaction};
+
+/// A backend that runs transactions.
+///
+/// Savepoints are nested inside a transaction: `savepoint` is called after
+/// `begin`, and each `savepoint` is paired with `release` or
+/// `rollback_to_savepoint`.
+pub trait TxBackend {
+    /// The context passed to the transactions
+    type Ctx;
+    /// The error of the backend
+    type Error;
+
+    /// The context of the running transaction
+    fn ctx(&mut self) -> &mut Self::Ctx;
+
+    /// Whether a transaction is running
+    fn in_transaction(&mut self) -> Result<bool, Self::Error>;
+
+    /// Open a new connection to run a transaction separately, used by
+    /// `requires_new`. Not supported by default.
+    fn new_backend(&mut self) -> Result<Self, PropagationError>
+    where
+        Self: Sized,
+    {
+        Err(PropagationError::Unsupported)
+    }
+
+    /// Begin a transaction
+    fn begin(&mut self) -> Result<(), Self::Error>;
+
+    /// Commit the current transaction
+    fn commit(&mut self) -> Result<(), Self::Error>;
+
+    /// Roll back the current transaction
+    fn rollback(&mut self) -> Result<(), Self::Error>;
+
+    /// Set a savepoint in the current transaction
+    fn savepoint(&mut self) -> Result<(), Self::Error>;
+
+    /// Release the last savepoint, keeping its changes
+    fn release(&mut self) -> Result<(), Self::Error>;
+
+    /// Roll back to the last savepoint and release it
+    fn rollback_to_savepoint(&mut self) -> Result<(), Self::Error>;
+
+    /// The hooks registered in the context, called by the runners.
+    /// No hooks by default.
+    fn hooks_mut(&mut self) -> Option<&mut Hooks> {
+        None
+    }
+}
+
+impl<B> TxBackend for &mut B
+where
+    B: ?Sized + TxBackend,
+{
+    type Ctx = B::Ctx;
+    type Error = B::Error;
+
+    fn ctx(&mut self) -> &mut Self::Ctx {
+        (**self).ctx()
+    }
+
+    fn in_transaction(&mut self) -> Result<bool, Self::Error> {
+        (**self).in_transaction()
+    }
+
+    fn begin(&mut self) -> Result<(), Self::Error> {
+        (**self).begin()
+    }
+
+    fn commit(&mut self) -> Result<(), Self::Error> {
+        (**self).commit()
+    }
+
+    fn rollback(&mut self) -> Result<(), Self::Error> {
+        (**self).rollback()
+    }
+
+    fn savepoint(&mut self) -> Result<(), Self::Error> {
+        (**self).savepoint()
+    }
+
+    fn release(&mut self) -> Result<(), Self::Error> {
+        (**self).release()
+    }
+
+    fn rollback_to_savepoint(&mut self) -> Result<(), Self::Error> {
+        (**self).rollback_to_savepoint()
+    }
+
+    fn hooks_mut(&mut self) -> Option<&mut Hooks> {
+        (**self).hooks_mut()
+    }
+}
+
+// the operations of the backend followed by the hooks
+
+fn commit<B: TxBackend>(backend: &mut B) -> Result<(), B::Error> {
+    let ret = backend.commit();
+    if let Some(hooks) = backend.hooks_mut() {
+        match ret {
+            Ok(()) => hooks.commit(),
+            // the transaction is not committed
+            Err(_) => hooks.rollback(),
+        }
+    }
+    ret
+}
+
+fn rollback<B: TxBackend>(backend: &mut B) -> Result<(), B::Error> {
+    let ret = backend.rollback();
+    if let Some(hooks) = backend.hooks_mut() {
+        hooks.rollback();
+    }
+    ret
+}
+
+fn savepoint<B: TxBackend>(backend: &mut B) -> Result<(), B::Error> {
+    backend.savepoint()?;
+    if let Some(hooks) = backend.hooks_mut() {
+        hooks.savepoint();
+    }
+    Ok(())
+}
+
+fn release<B: TxBackend>(backend: &mut B) -> Result<(), B::Error> {
+    backend.release()?;
+    if let Some(hooks) = backend.hooks_mut() {
+        hooks.release();
+    }
+    Ok(())
+}
+
+fn rollback_to_savepoint<B: TxBackend>(backend: &mut B) -> Result<(), B::Error> {
+    let ret = backend.rollback_to_savepoint();
+    if let Some(hooks) = backend.hooks_mut() {
+        hooks.rollback_to_savepoint();
+    }
+    ret
+}
+
+/// Run the transaction on the backend. The transaction is committed if it
+/// succeeds and rolled back otherwise.
+/// If the propagation of the transaction is set by `required`, `nested` and
+/// so on, the transaction begins and finishes as specified instead.
+pub fn run<B, Tx>(backend: &mut B, tx: Tx) -> Result<Tx::Item, Tx::Err>
+where
+    B: TxBackend,
+    Tx: Transaction<Ctx = B::Ctx>,
+    Tx::Err: From<B::Error>,
+{
+    match tx.propagation() {
+        Some(_) => tx.run(backend.ctx()),
+        None => run_in_transaction(backend, tx),
+    }
+}
+
+// begin a transaction regardless of the propagation
+pub(crate) fn run_in_transaction<B, Tx>(backend: &mut B, tx: Tx) -> Result<Tx::Item, Tx::Err>
+where
+    B: TxBackend,
+    Tx: Transaction<Ctx = B::Ctx>,
+    Tx::Err: From<B::Error>,
+{
+    backend.begin()?;
+    let ret = tx.run(backend.ctx());
+    finish(backend, ret)
+}
+
+/// Run the transaction on the backend but do not commit it.
+/// Panics if the transaction returns an Err.
+/// This is usefull for testing
+pub fn test_run<B, Tx>(backend: &mut B, tx: Tx) -> Tx::Item
+where
+    B: TxBackend,
+    Tx: Transaction<Ctx = B::Ctx>,
+{
+    if backend.begin().is_err() {
+        panic!("could not begin a transaction");
+    }
+    let ret = tx.run(backend.ctx());
+    if rollback(backend).is_err() {
+        panic!("could not rollback the transaction");
+    }
+    match ret {
+        Ok(t) => t,
+        Err(_) => panic!("Transaction did not succeed"),
+    }
+}
+
+/// Run the transaction on the backend and always roll it back, returning the
+/// result that would be committed.
+pub fn dry_run<B, Tx>(backend: &mut B, tx: Tx) -> Result<Tx::Item, Tx::Err>
+where
+    B: TxBackend,
+    Tx: Transaction<Ctx = B::Ctx>,
+    Tx::Err: From<B::Error>,
+{
+    backend.begin()?;
+    let ret = tx.run(backend.ctx());
+    rollback(backend)?;
+    ret
+}
+
+/// Run the transaction in a savepoint of the current transaction. If the
+/// transaction fails, only its changes are rolled back.
+pub fn run_in_savepoint<B, Tx>(backend: &mut B, tx: Tx) -> Result<Tx::Item, Tx::Err>
+where
+    B: TxBackend,
+    Tx: Transaction<Ctx = B::Ctx>,
+    Tx::Err: From<B::Error>,
+{
+    savepoint(backend)?;
+    match tx.run(backend.ctx()) {
+        Ok(t) => {
+            release(backend)?;
+            Ok(t)
+        }
+        Err(e) => {
+            rollback_to_savepoint(backend)?;
+            Err(e)
+        }
+    }
+}
+
+/// Commit or roll back the transaction begun on the backend according to the
+/// result. This is for the runners that begin transactions by themselves.
+pub fn finish<B, T, E>(backend: &mut B, ret: Result<T, E>) -> Result<T, E>
+where
+    B: TxBackend,
+    E: From<B::Error>,
+{
+    match ret {
+        Ok(t) => {
+            commit(backend)?;
+            Ok(t)
+        }
+        Err(e) => {
+            rollback(backend)?;
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::rc::Rc;
+
+use {Plan, Transaction};
+
+/// A data source that can load many values at once, e.g. by
+/// `WHERE id IN (...)`.
+/// Transactions made by `fetch` load values through it, and the ones in the
+/// same `join`, `join3`, `join4` or `join_all` are coalesced into a single
+/// `load`.
+pub trait BatchLoader {
+    /// The context of the transactions
+    type Ctx;
+    /// The key of values, such as an id
+    type Key: Eq + Hash + Clone;
+    /// The loaded value
+    type Value: Clone;
+    /// The error of loading. It is cloned for every `fetch` in the batch
+    /// when the load fails.
+    type Err: Clone;
+
+    /// Load the values of the keys. Keys without values are omitted from the
+    /// result. The keys are distinct.
+    fn load(
+        &self,
+        ctx: &mut Self::Ctx,
+        keys: &[Self::Key],
+    ) -> Result<HashMap<Self::Key, Self::Value>, Self::Err>;
+}
+
+/// Load the value of the key through the loader.
+///
+/// The `fetch`es in a join are loaded before the transactions of the join
+/// are run, so they see the values before the changes made in the join.
+/// `fetch`es are found through `map`, `and_then` (only the first
+/// transaction), `err_into`, `named`, `memo`, `memo_by`, `required`,
+/// `nested`, boxes and nested joins. If the load fails, every `fetch` of the
+/// loader returns the error.
+///
+/// ```
+/// # extern crate transaction;
+/// # use std::collections::HashMap;
+/// # use transaction::prelude::*;
+/// # use transaction::{fetch, BatchLoader};
+/// struct Users;
+///
+/// impl BatchLoader for Users {
+///     // count the number of queries
+///     type Ctx = usize;
+///     type Key = i64;
+///     type Value = String;
+///     type Err = ();
+///
+///     fn load(&self, queries: &mut usize, ids: &[i64]) -> Result<HashMap<i64, String>, ()> {
+///         *queries += 1;
+///         Ok(ids.iter().map(|id| (*id, format!("user{}", id))).collect())
+///     }
+/// }
+///
+/// # fn main() {
+/// let tx = join_all((0..10).map(|id| fetch(&Users, id).map(Option::unwrap)));
+/// let mut queries = 0;
+/// let users = tx.run(&mut queries).unwrap();
+/// assert_eq!(users[3], "user3");
+/// assert_eq!(queries, 1);
+///
+/// let tx = fetch(&Users, 1).join(fetch(&Users, 2).map(Option::unwrap));
+/// assert_eq!(tx.run(&mut queries).unwrap().1, "user2");
+/// assert_eq!(queries, 2);
+/// # }
+/// ```
+pub fn fetch<L>(loader: &L, key: L::Key) -> Fetch<'_, L>
+where
+    L: BatchLoader,
+{
+    Fetch {
+        loader: loader,
+        key: key,
+    }
+}
+
+/// The result of `fetch`
+#[derive(Debug)]
+#[must_use]
+pub struct Fetch<'a, L: 'a + BatchLoader> {
+    loader: &'a L,
+    key: L::Key,
+}
+
+impl<'a, L> Transaction for Fetch<'a, L>
+where
+    L: BatchLoader,
+    L::Key: 'static,
+    L::Value: 'static,
+    L::Err: 'static,
+{
+    type Ctx = L::Ctx;
+    type Item = Option<L::Value>;
+    type Err = L::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let mut values = self.loader.load(ctx, ::std::slice::from_ref(&self.key))?;
+        Ok(values.remove(&self.key))
+    }
+
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        let pending = batch.pending(self.loader);
+        pending.keys.borrow_mut().insert(self.key.clone());
+    }
+
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
+        let pending = match batch.find::<L>(self.loader) {
+            Some(pending) => pending,
+            None => return self.run(ctx),
+        };
+        let loaded = pending.loaded.borrow();
+        match *loaded {
+            Some(Ok(ref values)) => Ok(values.get(&self.key).cloned()),
+            // every fetch reports the error of the load
+            Some(Err(ref e)) => Err(e.clone()),
+            None => {
+                drop(loaded);
+                self.run(ctx)
+            }
+        }
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("fetch")
+    }
+}
+
+/// The `fetch`es collected from the transactions of a join, loaded at once
+/// by `load`. See `Transaction::prefetch`.
+pub struct Batch<'b, Ctx> {
+    groups: Vec<Group<'b, Ctx>>,
+}
+
+// the fetches of a loader
+struct Group<'b, Ctx> {
+    // the address of the loader
+    loader: usize,
+    pending: Rc<dyn Any>,
+    load: Box<dyn Fn(&mut Ctx) + 'b>,
+}
+
+struct Pending<K, V, E> {
+    keys: RefCell<HashSet<K>>,
+    loaded: RefCell<Option<Result<HashMap<K, V>, E>>>,
+}
+
+impl<'b, Ctx> Batch<'b, Ctx> {
+    /// Create an empty batch
+    pub fn new() -> Self {
+        Batch { groups: Vec::new() }
+    }
+
+    /// Whether no `fetch`es are collected
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Load the collected `fetch`es, one `BatchLoader::load` for each loader.
+    /// The errors are returned by the `fetch`es when they are run.
+    pub fn load(&self, ctx: &mut Ctx) {
+        for group in &self.groups {
+            (group.load)(ctx);
+        }
+    }
+
+    fn find<L>(&self, loader: &L) -> Option<&Pending<L::Key, L::Value, L::Err>>
+    where
+        L: BatchLoader,
+        L::Key: 'static,
+        L::Value: 'static,
+        L::Err: 'static,
+    {
+        let address = loader as *const L as usize;
+        self.groups
+            .iter()
+            .filter(|g| g.loader == address)
+            .filter_map(|g| g.pending.downcast_ref())
+            .next()
+    }
+
+    fn pending<L>(&mut self, loader: &'b L) -> &Pending<L::Key, L::Value, L::Err>
+    where
+        L: BatchLoader<Ctx = Ctx>,
+        L::Key: 'static,
+        L::Value: 'static,
+        L::Err: 'static,
+    {
+        if self.find(loader).is_none() {
+            let pending = Rc::new(Pending {
+                keys: RefCell::new(HashSet::new()),
+                loaded: RefCell::new(None),
+            });
+            let p = pending.clone();
+            self.groups.push(Group {
+                loader: loader as *const L as usize,
+                pending: pending,
+                load: Box::new(move |ctx| {
+                    let keys: Vec<_> = p.keys.borrow().iter().cloned().collect();
+                    *p.loaded.borrow_mut() = Some(loader.load(ctx, &keys));
+                }),
+            });
+        }
+        self.find(loader).expect("the pending fetches of the loader are added")
+    }
+}
+
+impl<'b, Ctx> Default for Batch<'b, Ctx> {
+    fn default() -> Self {
+        Batch::new()
+    }
+}
+
+impl<'b, Ctx> ::std::fmt::Debug for Batch<'b, Ctx> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("Batch")
+            .field("loaders", &self.groups.len())
+            .finish()
+    }
+}
@@ -1,4 +1,4 @@
-use Transaction;
+use {Plan, Transaction};
 
 /// BranchBuilder
 #[derive(Debug)]
@@ -46,4 +46,11 @@ where
             Branch::B2(ref tx) => tx.run(ctx),
         }
     }
+
+    fn describe(&self) -> Plan {
+        match *self {
+            Branch::B1(ref tx) => Plan::node("branch: first", vec![tx.describe()]),
+            Branch::B2(ref tx) => Plan::node("branch: second", vec![tx.describe()]),
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use Transaction;
+use {Plan, Transaction};
 
 /// Branch3Builder
 #[derive(Debug)]
@@ -56,4 +56,12 @@ where
             Branch3::B3(ref tx) => tx.run(ctx),
         }
     }
+
+    fn describe(&self) -> Plan {
+        match *self {
+            Branch3::B1(ref tx) => Plan::node("branch3: first", vec![tx.describe()]),
+            Branch3::B2(ref tx) => Plan::node("branch3: second", vec![tx.describe()]),
+            Branch3::B3(ref tx) => Plan::node("branch3: third", vec![tx.describe()]),
+        }
+    }
 }
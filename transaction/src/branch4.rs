@@ -1,4 +1,4 @@
-use Transaction;
+use {Plan, Transaction};
 
 /// Branch4Builder
 #[derive(Debug)]
@@ -68,4 +68,13 @@ where
             Branch4::B4(ref tx) => tx.run(ctx),
         }
     }
+
+    fn describe(&self) -> Plan {
+        match *self {
+            Branch4::B1(ref tx) => Plan::node("branch4: first", vec![tx.describe()]),
+            Branch4::B2(ref tx) => Plan::node("branch4: second", vec![tx.describe()]),
+            Branch4::B3(ref tx) => Plan::node("branch4: third", vec![tx.describe()]),
+            Branch4::B4(ref tx) => Plan::node("branch4: fourth", vec![tx.describe()]),
+        }
+    }
 }
@@ -0,0 +1,473 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+use {IntoTransaction, Plan, Transaction};
+
+/// A cache shared between runs. Transactions read it by `cached` and change
+/// it by `cache_insert` and `cache_invalidate`. The changes are recorded in
+/// the `CacheLog` of the context and applied after the transaction is
+/// committed, so the cache never holds uncommitted data.
+///
+/// Each key has a version bumped by committed inserts and invalidations. A
+/// value read from the transaction of `cached` is not written back if the
+/// key has been changed since the miss, so a slow run cannot overwrite a
+/// newer invalidation with a stale value. `clear` forgets the versions as
+/// well, and the values read before it are not written back.
+#[derive(Debug)]
+pub struct SharedCache<K, V> {
+    map: Arc<Mutex<Entries<K, V>>>,
+}
+
+#[derive(Debug)]
+struct Entries<K, V> {
+    values: HashMap<K, V>,
+    // bumped by `clear`, which forgets the versions of the keys
+    generation: u64,
+    // the keys never changed since the last `clear` are at version 0
+    versions: HashMap<K, u64>,
+}
+
+// the generation of the cache and the version of a key
+type Version = (u64, u64);
+
+impl<K, V> Entries<K, V>
+where
+    K: Eq + Hash,
+{
+    fn version(&self, key: &K) -> Version {
+        (self.generation, self.versions.get(key).cloned().unwrap_or(0))
+    }
+}
+
+impl<K, V> SharedCache<K, V>
+where
+    K: Eq + Hash,
+    V: Clone,
+{
+    /// Create an empty cache
+    pub fn new() -> Self {
+        SharedCache {
+            map: Arc::new(Mutex::new(Entries {
+                values: HashMap::new(),
+                generation: 0,
+                versions: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Get the committed value of the key
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.lock().values.get(key).cloned()
+    }
+
+    /// Forget all the values
+    pub fn clear(&self) {
+        let mut entries = self.lock();
+        entries.values.clear();
+        // the pending fills are checked by the generation instead
+        entries.versions.clear();
+        entries.generation += 1;
+    }
+
+    fn lock(&self) -> ::std::sync::MutexGuard<'_, Entries<K, V>> {
+        // the map is consistent even if a thread panicked while holding it
+        self.map.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // the committed value of the key and the version of the key
+    fn get_versioned(&self, key: &K) -> (Option<V>, Version) {
+        let entries = self.lock();
+        (entries.values.get(key).cloned(), entries.version(key))
+    }
+
+    // identifies the cache in `CacheLog`
+    fn address(&self) -> usize {
+        &*self.map as *const Mutex<Entries<K, V>> as usize
+    }
+}
+
+impl<K, V> SharedCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    fn apply(&self, key: K, write: Write<V>) {
+        let mut entries = self.lock();
+        match write {
+            Write::Fill(v, version) => if entries.version(&key) == version {
+                entries.values.insert(key, v);
+            },
+            Write::Insert(v) => {
+                *entries.versions.entry(key.clone()).or_insert(0) += 1;
+                entries.values.insert(key, v);
+            }
+            Write::Invalidate => {
+                entries.values.remove(&key);
+                *entries.versions.entry(key).or_insert(0) += 1;
+            }
+        }
+    }
+}
+
+impl<K, V> Clone for SharedCache<K, V> {
+    fn clone(&self) -> Self {
+        SharedCache { map: self.map.clone() }
+    }
+}
+
+impl<K, V> Default for SharedCache<K, V>
+where
+    K: Eq + Hash,
+    V: Clone,
+{
+    fn default() -> Self {
+        SharedCache::new()
+    }
+}
+
+// a change to a cache
+struct Change<K, V> {
+    cache: SharedCache<K, V>,
+    key: K,
+    write: Write<V>,
+}
+
+enum Write<V> {
+    // the value read by `cached` at the version of the key
+    Fill(V, Version),
+    Insert(V),
+    Invalidate,
+}
+
+impl<V: Clone> Write<V> {
+    fn value(&self) -> Option<V> {
+        match *self {
+            Write::Fill(ref v, _) | Write::Insert(ref v) => Some(v.clone()),
+            Write::Invalidate => None,
+        }
+    }
+}
+
+trait PendingChange {
+    fn apply(self: Box<Self>);
+    fn as_any(&self) -> &dyn Any;
+    // remove the change, which is the latest one of its key, from the index
+    fn unindex(&self, index: &mut Index);
+}
+
+// the positions of the changes in `CacheLog::changes` by the address of the
+// cache, holding `HashMap<K, Vec<usize>>` for the cache
+type Index = HashMap<usize, Box<dyn Any>>;
+
+impl<K, V> PendingChange for Change<K, V>
+where
+    K: Eq + Hash + Clone + 'static,
+    V: Clone + 'static,
+{
+    fn apply(self: Box<Self>) {
+        let Change { cache, key, write } = *self;
+        cache.apply(key, write)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn unindex(&self, index: &mut Index) {
+        let positions = index
+            .get_mut(&self.cache.address())
+            .and_then(|positions| positions.downcast_mut::<HashMap<K, Vec<usize>>>());
+        if let Some(positions) = positions {
+            if let Some(p) = positions.get_mut(&self.key) {
+                p.pop();
+                if p.is_empty() {
+                    positions.remove(&self.key);
+                }
+            }
+        }
+    }
+}
+
+/// The changes to `SharedCache`s made in a run, held by a context.
+/// Backends call `commit` after committing the transaction and the other
+/// methods along with rollbacks and savepoints.
+#[derive(Default)]
+pub struct CacheLog {
+    changes: Vec<Box<dyn PendingChange>>,
+    index: Index,
+    savepoints: Vec<usize>,
+}
+
+impl CacheLog {
+    /// Create an empty log
+    pub fn new() -> Self {
+        CacheLog::default()
+    }
+
+    /// Apply the changes to the caches
+    pub fn commit(&mut self) {
+        self.savepoints.clear();
+        self.index.clear();
+        for change in self.changes.drain(..) {
+            change.apply();
+        }
+    }
+
+    /// Drop the changes
+    pub fn rollback(&mut self) {
+        self.savepoints.clear();
+        self.index.clear();
+        self.changes.clear();
+    }
+
+    /// Mark a savepoint
+    pub fn savepoint(&mut self) {
+        self.savepoints.push(self.changes.len());
+    }
+
+    /// Keep the changes since the last savepoint
+    pub fn release(&mut self) {
+        self.savepoints.pop();
+    }
+
+    /// Drop the changes since the last savepoint
+    pub fn rollback_to_savepoint(&mut self) {
+        if let Some(len) = self.savepoints.pop() {
+            for change in self.changes.drain(len..).rev() {
+                change.unindex(&mut self.index);
+            }
+        }
+    }
+
+    fn record<K, V>(&mut self, cache: &SharedCache<K, V>, key: K, write: Write<V>)
+    where
+        K: Eq + Hash + Clone + 'static,
+        V: Clone + 'static,
+    {
+        let position = self.changes.len();
+        self.index
+            .entry(cache.address())
+            .or_insert_with(|| Box::new(HashMap::<K, Vec<usize>>::new()))
+            .downcast_mut::<HashMap<K, Vec<usize>>>()
+            .expect("the index of a cache is HashMap<K, Vec<usize>>")
+            .entry(key.clone())
+            .or_insert_with(Vec::new)
+            .push(position);
+        self.changes.push(Box::new(Change {
+            cache: cache.clone(),
+            key: key,
+            write: write,
+        }))
+    }
+
+    // the latest change to the key made in this run
+    fn lookup<K, V>(&self, cache: &SharedCache<K, V>, key: &K) -> Option<Option<V>>
+    where
+        K: Eq + Hash + 'static,
+        V: Clone + 'static,
+    {
+        let position = *self
+            .index
+            .get(&cache.address())?
+            .downcast_ref::<HashMap<K, Vec<usize>>>()?
+            .get(key)?
+            .last()?;
+        self.changes[position]
+            .as_any()
+            .downcast_ref::<Change<K, V>>()
+            .map(|c| c.write.value())
+    }
+}
+
+impl ::std::fmt::Debug for CacheLog {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("CacheLog")
+            .field("changes", &self.changes.len())
+            .field("savepoints", &self.savepoints)
+            .finish()
+    }
+}
+
+/// Contexts that can record changes to `SharedCache`s
+pub trait CacheCtx {
+    /// The changes made in the current run
+    fn cache_log(&mut self) -> &mut CacheLog;
+}
+
+/// Read the value of the key from the cache, or run the transaction and
+/// cache its result after the commit.
+/// Values changed earlier in the same run are seen.
+///
+/// ```
+/// # extern crate transaction;
+/// # use transaction::prelude::*;
+/// # use transaction::{cache_invalidate, cached, CacheCtx, CacheLog, SharedCache};
+/// struct Ctx {
+///     cache_log: CacheLog,
+/// }
+///
+/// impl CacheCtx for Ctx {
+///     fn cache_log(&mut self) -> &mut CacheLog {
+///         &mut self.cache_log
+///     }
+/// }
+///
+/// # fn main() {
+/// let users = SharedCache::new();
+/// let find_user = |id: i64| cached(&users, id, ok::<Ctx, _, ()>(format!("user{}", id)));
+/// let mut ctx = Ctx { cache_log: CacheLog::new() };
+///
+/// // rolled back
+/// find_user(1).run(&mut ctx).unwrap();
+/// ctx.cache_log.rollback();
+/// assert_eq!(users.get(&1), None);
+///
+/// // committed
+/// find_user(1).run(&mut ctx).unwrap();
+/// ctx.cache_log.commit();
+/// assert_eq!(users.get(&1), Some("user1".to_string()));
+///
+/// cache_invalidate::<_, _, String, ()>(&users, 1).run(&mut ctx).unwrap();
+/// // still there until the commit
+/// assert_eq!(users.get(&1), Some("user1".to_string()));
+/// ctx.cache_log.commit();
+/// assert_eq!(users.get(&1), None);
+/// # }
+/// ```
+pub fn cached<Ctx, A, K, V>(cache: &SharedCache<K, V>, key: K, a: A) -> Cached<K, V, A::Tx>
+where
+    A: IntoTransaction<Ctx, Item = V>,
+{
+    Cached {
+        cache: cache.clone(),
+        key: key,
+        tx: a.into_transaction(),
+    }
+}
+
+/// The result of `cached`
+#[derive(Debug)]
+#[must_use]
+pub struct Cached<K, V, Tx> {
+    cache: SharedCache<K, V>,
+    key: K,
+    tx: Tx,
+}
+
+impl<K, V, Tx> Transaction for Cached<K, V, Tx>
+where
+    Tx: Transaction<Item = V>,
+    Tx::Ctx: CacheCtx,
+    K: Eq + Hash + Clone + 'static,
+    V: Clone + 'static,
+{
+    type Ctx = Tx::Ctx;
+    type Item = V;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let Cached { ref cache, ref key, ref tx } = *self;
+        let version = match ctx.cache_log().lookup(cache, key) {
+            Some(Some(v)) => return Ok(v),
+            // invalidated in this run, so the value is written back as a
+            // change made in this run
+            Some(None) => None,
+            None => match cache.get_versioned(key) {
+                (Some(v), _) => return Ok(v),
+                (None, version) => Some(version),
+            },
+        };
+        let v = tx.run(ctx)?;
+        let write = match version {
+            Some(version) => Write::Fill(v.clone(), version),
+            None => Write::Insert(v.clone()),
+        };
+        ctx.cache_log().record(cache, key.clone(), write);
+        Ok(v)
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::node("cached", vec![self.tx.describe()])
+    }
+}
+
+/// Set the value of the key after the commit
+pub fn cache_insert<Ctx, K, V, E>(cache: &SharedCache<K, V>, key: K, value: V) -> CacheInsert<Ctx, K, V, E> {
+    CacheInsert {
+        cache: cache.clone(),
+        key: key,
+        value: value,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `cache_insert`
+#[derive(Debug)]
+#[must_use]
+pub struct CacheInsert<Ctx, K, V, E> {
+    cache: SharedCache<K, V>,
+    key: K,
+    value: V,
+    _phantom: PhantomData<fn() -> (Ctx, E)>,
+}
+
+impl<Ctx, K, V, E> Transaction for CacheInsert<Ctx, K, V, E>
+where
+    Ctx: CacheCtx,
+    K: Eq + Hash + Clone + 'static,
+    V: Clone + 'static,
+{
+    type Ctx = Ctx;
+    type Item = ();
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.cache_log().record(&self.cache, self.key.clone(), Write::Insert(self.value.clone()));
+        Ok(())
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("cache_insert")
+    }
+}
+
+/// Remove the value of the key after the commit
+pub fn cache_invalidate<Ctx, K, V, E>(cache: &SharedCache<K, V>, key: K) -> CacheInvalidate<Ctx, K, V, E> {
+    CacheInvalidate {
+        cache: cache.clone(),
+        key: key,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `cache_invalidate`
+#[derive(Debug)]
+#[must_use]
+pub struct CacheInvalidate<Ctx, K, V, E> {
+    cache: SharedCache<K, V>,
+    key: K,
+    _phantom: PhantomData<fn() -> (Ctx, E)>,
+}
+
+impl<Ctx, K, V, E> Transaction for CacheInvalidate<Ctx, K, V, E>
+where
+    Ctx: CacheCtx,
+    K: Eq + Hash + Clone + 'static,
+    V: Clone + 'static,
+{
+    type Ctx = Ctx;
+    type Item = ();
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.cache_log().record(&self.cache, self.key.clone(), Write::Invalidate);
+        Ok(())
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("cache_invalidate")
+    }
+}
@@ -0,0 +1,212 @@
+use std::any::Any;
+use std::marker::PhantomData;
+
+use {BoxTx, IntoTransaction, Plan, SendBoxTx, Transaction};
+
+/// Start a chain of sequential steps. Steps are added by `Chain::and_then`
+/// and have the same semantics as `Transaction::and_then`, but they are kept
+/// in a vector and run in a loop. Use this to build long sequences, e.g. in a
+/// loop, that would overflow the stack or make the type too deep with nested
+/// `and_then`s.
+///
+/// Each step may change the item type. The items are passed between the
+/// steps as `Box<dyn Any>`, so they must be `'static`.
+///
+/// ```
+/// # extern crate transaction;
+/// # use transaction::prelude::*;
+/// # fn main() {
+/// let mut tx = chain(ok::<(), i64, ()>(0));
+/// for i in 0..100_000 {
+///     tx = tx.and_then(move |sum| ok(sum + i));
+/// }
+/// let tx = tx.and_then(|sum| ok(sum.to_string()));
+/// assert_eq!(tx.run(&mut ()), Ok("4999950000".to_string()));
+/// # }
+/// ```
+pub fn chain<'a, Ctx, A>(a: A) -> Chain<'a, Ctx, A::Item, A::Err>
+where
+    A: IntoTransaction<Ctx>,
+    A::Tx: 'a,
+    A::Item: 'static,
+{
+    Chain {
+        first: Erase(a.into_transaction()).boxed(),
+        steps: Vec::new(),
+        _phantom: PhantomData,
+    }
+}
+
+/// Same as `chain` but the chain is `Send`, so the first transaction and
+/// the steps must be `Send`.
+pub fn chain_send<'a, Ctx, A>(a: A) -> SendChain<'a, Ctx, A::Item, A::Err>
+where
+    A: IntoTransaction<Ctx>,
+    A::Tx: Send + 'a,
+    A::Item: 'static,
+{
+    SendChain {
+        first: Erase(a.into_transaction()).boxed_send(),
+        steps: Vec::new(),
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `chain`
+#[must_use]
+pub struct Chain<'a, Ctx, T, E> {
+    first: BoxTx<'a, Ctx, Value, E>,
+    steps: Vec<Step<'a, Ctx, E>>,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+/// The result of `chain_send`
+#[must_use]
+pub struct SendChain<'a, Ctx, T, E> {
+    first: SendBoxTx<'a, Ctx, Value, E>,
+    steps: Vec<SendStep<'a, Ctx, E>>,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+// the item of a step
+type Value = Box<dyn Any>;
+
+type Step<'a, Ctx, E> = Box<dyn Fn(Value, &mut Ctx) -> Result<Value, E> + 'a>;
+
+type SendStep<'a, Ctx, E> = Box<dyn Fn(Value, &mut Ctx) -> Result<Value, E> + Send + 'a>;
+
+// the item of the previous step
+fn unbox<T: 'static>(t: Value) -> T {
+    *t.downcast()
+        .expect("the item of a step has the type of the chain")
+}
+
+impl<'a, Ctx, T, E> Chain<'a, Ctx, T, E>
+where
+    T: 'static,
+{
+    /// Add a step taking the previous successful value
+    pub fn and_then<F, B>(self, f: F) -> Chain<'a, Ctx, B::Item, E>
+    where
+        F: Fn(T) -> B + 'a,
+        B: IntoTransaction<Ctx, Err = E>,
+        B::Item: 'static,
+    {
+        let Chain { first, mut steps, .. } = self;
+        steps.push(Box::new(move |t, ctx| step(&f, t, ctx)));
+        Chain {
+            first: first,
+            steps: steps,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, Ctx, T, E> SendChain<'a, Ctx, T, E>
+where
+    T: 'static,
+{
+    /// Add a step taking the previous successful value
+    pub fn and_then<F, B>(self, f: F) -> SendChain<'a, Ctx, B::Item, E>
+    where
+        F: Fn(T) -> B + Send + 'a,
+        B: IntoTransaction<Ctx, Err = E>,
+        B::Item: 'static,
+    {
+        let SendChain { first, mut steps, .. } = self;
+        steps.push(Box::new(move |t, ctx| step(&f, t, ctx)));
+        SendChain {
+            first: first,
+            steps: steps,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, Ctx, T, E> Transaction for Chain<'a, Ctx, T, E>
+where
+    T: 'static,
+{
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        run_steps(&self.first, &self.steps, ctx).map(unbox)
+    }
+
+    fn describe(&self) -> Plan {
+        describe_steps(&self.first, &self.steps)
+    }
+}
+
+impl<'a, Ctx, T, E> Transaction for SendChain<'a, Ctx, T, E>
+where
+    T: 'static,
+{
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        run_steps(&self.first, &self.steps, ctx).map(unbox)
+    }
+
+    fn describe(&self) -> Plan {
+        describe_steps(&self.first, &self.steps)
+    }
+}
+
+// a step of `and_then`, taking the item of the previous step
+fn step<Ctx, T, E, F, B>(f: &F, t: Value, ctx: &mut Ctx) -> Result<Value, E>
+where
+    T: 'static,
+    F: Fn(T) -> B,
+    B: IntoTransaction<Ctx, Err = E>,
+    B::Item: 'static,
+{
+    f(unbox(t))
+        .into_transaction()
+        .run(ctx)
+        .map(|b| Box::new(b) as Value)
+}
+
+// the loop shared by `Chain` and `SendChain`, which differ only in the boxes
+fn run_steps<Ctx, E, Tx, S>(first: &Tx, steps: &[S], ctx: &mut Ctx) -> Result<Value, E>
+where
+    Tx: Transaction<Ctx = Ctx, Item = Value, Err = E>,
+    S: Fn(Value, &mut Ctx) -> Result<Value, E>,
+{
+    let mut t = first.run(ctx)?;
+    for step in steps {
+        t = step(t, ctx)?;
+    }
+    Ok(t)
+}
+
+fn describe_steps<Tx: Transaction, S>(first: &Tx, steps: &[S]) -> Plan {
+    let mut children = vec![first.describe()];
+    children.extend(steps.iter().map(|_| Plan::closure()));
+    Plan::node("chain", children)
+}
+
+// box the item of the first transaction
+struct Erase<Tx>(Tx);
+
+impl<Tx> Transaction for Erase<Tx>
+where
+    Tx: Transaction,
+    Tx::Item: 'static,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Value;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        self.0.run(ctx).map(|t| Box::new(t) as Value)
+    }
+
+    fn describe(&self) -> Plan {
+        self.0.describe()
+    }
+}
@@ -0,0 +1,44 @@
+use std::borrow::Cow;
+
+use {AnyTxError, IntoTransaction, Plan, Transaction};
+
+pub fn context<Ctx, A, C>(a: A, c: C) -> Context<A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+    A::Err: Into<AnyTxError>,
+    C: Into<Cow<'static, str>>,
+{
+    Context {
+        tx: a.into_transaction(),
+        context: c.into(),
+    }
+}
+
+
+/// The result of `context`
+#[derive(Debug)]
+#[must_use]
+pub struct Context<Tx> {
+    tx: Tx,
+    context: Cow<'static, str>,
+}
+
+impl<Tx> Transaction for Context<Tx>
+where
+    Tx: Transaction,
+    Tx::Err: Into<AnyTxError>,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = AnyTxError;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        self.tx
+            .run(ctx)
+            .map_err(|e| e.into().context(self.context.clone()))
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::node(format!("context: {}", self.context), vec![self.tx.describe()])
+    }
+}
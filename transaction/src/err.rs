@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use Transaction;
+use {Plan, Transaction};
 
 /// make a error transaction value.
 pub fn err<Ctx, T, E>(e: E) -> TxErr<Ctx, T, E> {
@@ -16,7 +16,7 @@ pub fn err<Ctx, T, E>(e: E) -> TxErr<Ctx, T, E> {
 #[must_use]
 pub struct TxErr<Ctx, T, E> {
     err: E,
-    _phantom: PhantomData<(Ctx, T)>,
+    _phantom: PhantomData<fn() -> (Ctx, T)>,
 }
 
 impl<Ctx, T, E> Transaction for TxErr<Ctx, T, E>
@@ -29,4 +29,8 @@ where
     fn run(&self, _ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
         Err(self.err.clone())
     }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("err")
+    }
 }
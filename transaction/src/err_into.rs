@@ -0,0 +1,49 @@
+use std::marker::PhantomData;
+
+use {Batch, IntoTransaction, Plan, Transaction};
+
+pub fn err_into<Ctx, A, E>(a: A) -> ErrInto<A::Tx, E>
+where
+    A: IntoTransaction<Ctx>,
+    A::Err: Into<E>,
+{
+    ErrInto {
+        tx: a.into_transaction(),
+        _phantom: PhantomData,
+    }
+}
+
+
+/// The result of `err_into`
+#[derive(Debug)]
+#[must_use]
+pub struct ErrInto<Tx, E> {
+    tx: Tx,
+    _phantom: PhantomData<fn() -> E>,
+}
+
+impl<Tx, E> Transaction for ErrInto<Tx, E>
+where
+    Tx: Transaction,
+    Tx::Err: Into<E>,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = E;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        self.tx.run(ctx).map_err(Into::into)
+    }
+
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        self.tx.prefetch(batch)
+    }
+
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
+        self.tx.run_prefetched(ctx, batch).map_err(Into::into)
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::node("err_into", vec![self.tx.describe()])
+    }
+}
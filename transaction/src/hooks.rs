@@ -0,0 +1,197 @@
+use std::marker::PhantomData;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+
+use {Plan, Transaction};
+
+/// The callbacks registered by `on_commit` and `on_rollback` during a run.
+/// The runners in `backend` call them after the transaction is committed or
+/// rolled back, for the backends returning them from `TxBackend::hooks_mut`.
+/// If a hook panics, the other hooks are still called and the panic is
+/// resumed after them.
+#[derive(Default)]
+pub struct Hooks {
+    on_commit: Vec<Box<dyn FnOnce()>>,
+    on_rollback: Vec<Box<dyn FnOnce()>>,
+    // the lengths of `on_commit` and `on_rollback` at each savepoint
+    savepoints: Vec<(usize, usize)>,
+}
+
+impl Hooks {
+    /// Create an empty set of hooks
+    pub fn new() -> Self {
+        Hooks::default()
+    }
+
+    /// Call the hooks of `on_commit` and forget the others
+    pub fn commit(&mut self) {
+        self.savepoints.clear();
+        self.on_rollback.clear();
+        call(mem::take(&mut self.on_commit));
+    }
+
+    /// Call the hooks of `on_rollback` and forget the others
+    pub fn rollback(&mut self) {
+        self.savepoints.clear();
+        self.on_commit.clear();
+        call(mem::take(&mut self.on_rollback));
+    }
+
+    /// Mark a savepoint
+    pub fn savepoint(&mut self) {
+        self.savepoints
+            .push((self.on_commit.len(), self.on_rollback.len()));
+    }
+
+    /// Keep the hooks registered since the last savepoint
+    pub fn release(&mut self) {
+        self.savepoints.pop();
+    }
+
+    /// Forget the `on_commit` hooks registered since the last savepoint and
+    /// call the `on_rollback` ones, as their changes are rolled back
+    pub fn rollback_to_savepoint(&mut self) {
+        if let Some((commit, rollback)) = self.savepoints.pop() {
+            self.on_commit.truncate(commit);
+            call(self.on_rollback.split_off(rollback));
+        }
+    }
+}
+
+// call every hook even if some of them panic, then resume the first panic
+fn call(hooks: Vec<Box<dyn FnOnce()>>) {
+    let mut panicked = None;
+    for f in hooks {
+        if let Err(p) = panic::catch_unwind(AssertUnwindSafe(f)) {
+            panicked.get_or_insert(p);
+        }
+    }
+    if let Some(p) = panicked {
+        panic::resume_unwind(p);
+    }
+}
+
+impl ::std::fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_commit", &self.on_commit.len())
+            .field("on_rollback", &self.on_rollback.len())
+            .field("savepoints", &self.savepoints)
+            .finish()
+    }
+}
+
+/// Contexts that can register hooks
+pub trait HookCtx {
+    /// The hooks registered in the current run
+    fn hooks(&mut self) -> &mut Hooks;
+}
+
+/// Call `f` after the transaction is committed, such as to send an email
+/// only when the changes are saved.
+///
+/// ```
+/// # extern crate transaction;
+/// # use std::cell::Cell;
+/// # use std::rc::Rc;
+/// # use transaction::prelude::*;
+/// # use transaction::{on_commit, HookCtx, Hooks};
+/// struct Ctx {
+///     hooks: Hooks,
+/// }
+///
+/// impl HookCtx for Ctx {
+///     fn hooks(&mut self) -> &mut Hooks {
+///         &mut self.hooks
+///     }
+/// }
+///
+/// # fn main() {
+/// let sent = Rc::new(Cell::new(false));
+/// let s = sent.clone();
+/// let tx = on_commit::<Ctx, _, ()>(move || s.set(true));
+/// let mut ctx = Ctx { hooks: Hooks::new() };
+///
+/// tx.run(&mut ctx).unwrap();
+/// ctx.hooks.rollback();
+/// assert!(!sent.get());
+///
+/// tx.run(&mut ctx).unwrap();
+/// ctx.hooks.commit();
+/// assert!(sent.get());
+/// # }
+/// ```
+pub fn on_commit<Ctx, F, E>(f: F) -> OnCommit<Ctx, F, E>
+where
+    Ctx: HookCtx,
+    F: Fn() + Clone + 'static,
+{
+    OnCommit {
+        f: f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `on_commit`
+#[derive(Debug)]
+#[must_use]
+pub struct OnCommit<Ctx, F, E> {
+    f: F,
+    _phantom: PhantomData<fn() -> (Ctx, E)>,
+}
+
+impl<Ctx, F, E> Transaction for OnCommit<Ctx, F, E>
+where
+    Ctx: HookCtx,
+    F: Fn() + Clone + 'static,
+{
+    type Ctx = Ctx;
+    type Item = ();
+    type Err = E;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.hooks().on_commit.push(Box::new(self.f.clone()));
+        Ok(())
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("on_commit")
+    }
+}
+
+/// Call `f` after the transaction, or the savepoint, is rolled back.
+pub fn on_rollback<Ctx, F, E>(f: F) -> OnRollback<Ctx, F, E>
+where
+    Ctx: HookCtx,
+    F: Fn() + Clone + 'static,
+{
+    OnRollback {
+        f: f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `on_rollback`
+#[derive(Debug)]
+#[must_use]
+pub struct OnRollback<Ctx, F, E> {
+    f: F,
+    _phantom: PhantomData<fn() -> (Ctx, E)>,
+}
+
+impl<Ctx, F, E> Transaction for OnRollback<Ctx, F, E>
+where
+    Ctx: HookCtx,
+    F: Fn() + Clone + 'static,
+{
+    type Ctx = Ctx;
+    type Item = ();
+    type Err = E;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        ctx.hooks().on_rollback.push(Box::new(self.f.clone()));
+        Ok(())
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("on_rollback")
+    }
+}
@@ -1,4 +1,4 @@
-use {IntoTransaction, Transaction};
+use {Batch, IntoTransaction, Plan, Transaction};
 
 pub fn join<Ctx, A: IntoTransaction<Ctx>, B: IntoTransaction<Ctx, Err = A::Err>>(
     a: A,
@@ -30,10 +30,27 @@ where
     type Err = Tx1::Err;
 
     fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        // load the `fetch`es of the transactions at once
+        let mut batch = Batch::new();
+        self.prefetch(&mut batch);
+        batch.load(ctx);
+        self.run_prefetched(ctx, &batch)
+    }
+
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        self.tx1.prefetch(batch);
+        self.tx2.prefetch(batch);
+    }
+
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
         let &Join { ref tx1, ref tx2, .. } = self;
-        match (tx1.run(ctx), tx2.run(ctx)) {
+        match (tx1.run_prefetched(ctx, batch), tx2.run_prefetched(ctx, batch)) {
             (Ok(r1), Ok(r2)) => Ok((r1, r2)),
             (Err(e), _) | (_, Err(e)) => Err(e),
         }
     }
+
+    fn describe(&self) -> Plan {
+        Plan::node("join", vec![self.tx1.describe(), self.tx2.describe()])
+    }
 }
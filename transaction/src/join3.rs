@@ -1,4 +1,4 @@
-use {IntoTransaction, Transaction};
+use {Batch, IntoTransaction, Plan, Transaction};
 
 pub fn join3<
     Ctx,
@@ -44,14 +44,32 @@ where
     type Err = Tx1::Err;
 
     fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        // load the `fetch`es of the transactions at once
+        let mut batch = Batch::new();
+        self.prefetch(&mut batch);
+        batch.load(ctx);
+        self.run_prefetched(ctx, &batch)
+    }
+
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        self.tx1.prefetch(batch);
+        self.tx2.prefetch(batch);
+        self.tx3.prefetch(batch);
+    }
+
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
         let &Join3 {
             ref tx1,
             ref tx2,
             ref tx3,
         } = self;
-        match (tx1.run(ctx), tx2.run(ctx), tx3.run(ctx)) {
+        match (tx1.run_prefetched(ctx, batch), tx2.run_prefetched(ctx, batch), tx3.run_prefetched(ctx, batch)) {
             (Ok(r1), Ok(r2), Ok(r3)) => Ok((r1, r2, r3)),
             (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => Err(e),
         }
     }
+
+    fn describe(&self) -> Plan {
+        Plan::node("join3", vec![self.tx1.describe(), self.tx2.describe(), self.tx3.describe()])
+    }
 }
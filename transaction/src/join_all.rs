@@ -1,4 +1,4 @@
-use {IntoTransaction, Transaction};
+use {Batch, IntoTransaction, Plan, Transaction};
 
 /// join a vec of transaction
 pub fn join_all<Ctx, I, B>(i: I) -> JoinAll<B::Tx>
@@ -29,10 +29,24 @@ where
     type Err = Tx::Err;
 
     fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
-        let vec = &self.vec;
+        // load the `fetch`es of the transactions at once
+        let mut batch = Batch::new();
+        self.prefetch(&mut batch);
+        batch.load(ctx);
+        self.run_prefetched(ctx, &batch)
+    }
+
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        for tx in &self.vec {
+            tx.prefetch(batch);
+        }
+    }
+
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
+        self.vec.iter().map(|tx| tx.run_prefetched(ctx, batch)).collect()
+    }
 
-        vec.iter()
-            .map(|tx| tx.run(ctx))
-            .collect::<Result<Vec<_>, _>>()
+    fn describe(&self) -> Plan {
+        Plan::node("join_all", self.vec.iter().map(Transaction::describe).collect())
     }
 }
@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use Transaction;
+use {Plan, Transaction};
 
 /// lazy evaluated transaction value.
 /// Note that inner function can be called many times.
@@ -19,7 +19,7 @@ where
 #[must_use]
 pub struct Lazy<Ctx, F> {
     f: F,
-    _phantom: PhantomData<Ctx>,
+    _phantom: PhantomData<fn() -> Ctx>,
 }
 
 impl<Ctx, T, E, F> Transaction for Lazy<Ctx, F>
@@ -32,4 +32,8 @@ where
     fn run(&self, _ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
         (self.f)()
     }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("lazy")
+    }
 }
@@ -16,6 +16,9 @@
 //! Another feature is it does DI of transaction. For database transaction, it
 //! means that it injects DB connection from the context.
 //!
+//! With the `macros` feature, `#[transactional]` is available to write
+//! transactions as ordinary functions.
+//!
 //! # Examples
 //!
 //! ```
@@ -23,39 +26,36 @@
 //! extern crate transaction;
 //!
 //! use self::transaction::prelude::*;
+//! use self::transaction::BoxTx;
 //!
 //! # struct FooConnection;
 //! # struct FooError;
 //! # #[derive(Clone)]struct User;
 //!
-//! // Since current rust doesn't support `impl Trait`, you need to make a
-//! // trait box
-//! // to return a trait value from a function.
-//! type BoxTx<'a, T> = Box<Transaction<
-//!                           Ctx = FooConnection,
-//!                           Item = T,
-//!                           Err =FooError>
-//!                         + 'a>;
-//!
-//! fn find_user<'a>(id: i64) -> BoxTx<'a, Option<User>> {
+//! // Return transactions by `impl Transaction`.
+//! // `TxFor<Ctx, T, E>` is a shorthand of
+//! // `Transaction<Ctx = Ctx, Item = T, Err = E>`.
+//! fn find_user(id: i64) -> impl TxFor<FooConnection, Option<User>, FooError> {
 //!     // connection is inejected from the context
-//!     with_ctx(move |cn: &mut FooConnection| {
+//!     with_ctx(move |cn: &mut FooConnection| -> Result<Option<User>, FooError> {
 //!         // ..
 //!         # let _ = (id, cn);
 //!         # unimplemented!()
-//!     }).boxed()
+//!     })
 //!
 //! }
 //!
-//! fn update_user<'a>(id: i64, name: &'a str) -> BoxTx<'a, Option<()>> {
-//!     with_ctx(move |cn: &mut FooConnection| {
+//! fn update_user<'a>(id: i64, name: &'a str)
+//!                    -> impl TxFor<FooConnection, Option<()>, FooError> + 'a {
+//!     with_ctx(move |cn: &mut FooConnection| -> Result<Option<()>, FooError> {
 //!         // ..
 //!         # let _ = (id, cn, name);
 //!         # unimplemented!()
-//!     }).boxed()
+//!     })
 //! }
 //!
-//! fn update_find_user<'a>(id: i64, name: &'a str) -> BoxTx<'a, Option<User>> {
+//! fn update_find_user<'a>(id: i64, name: &'a str)
+//!                         -> impl TxFor<FooConnection, Option<User>, FooError> + 'a {
 //!     update_user(id, name)
 //!         // transaction can be composed using `and_then`
 //!         .and_then(move |ret| match ret {
@@ -72,18 +72,42 @@
 //!                 // use `second` in the second arm of the brnach
 //!                 .second(),
 //!         })
-//!         // finally, box it to return `BoxTx`.
-//!         .boxed()
+//! }
+//!
+//! // If you need a trait object, for example to store transactions of
+//! // different types in a collection, use `boxed`.
+//! fn boxed_find_user<'a>(id: i64) -> BoxTx<'a, FooConnection, Option<User>, FooError> {
+//!     find_user(id).boxed()
 //! }
 //! # fn main() {}
 //! ```
 
+// struct literals spell out the field names, as in `f: f`
+#![allow(clippy::redundant_field_names)]
 
 #[cfg(feature = "mdo")]
 pub mod mdo;
+pub mod backend;
+
+#[cfg(feature = "macros")]
+#[allow(unused_imports)]
+#[macro_use]
+extern crate transaction_macros;
+#[cfg(feature = "macros")]
+pub use transaction_macros::*;
+
+#[doc(hidden)]
+pub mod __private {
+    // used by `#[transactional]`
+    pub use transactional::*;
+}
+
+#[macro_use]
+mod tx_error;
 
 pub mod prelude {
-    pub use super::Transaction;
+    pub use super::{Transaction, TxFor};
+    pub use chain::chain;
     pub use err::err;
     pub use join_all::join_all;
     pub use lazy::lazy;
@@ -92,13 +116,21 @@ pub mod prelude {
     pub use repeat::repeat;
     pub use result::result;
     pub use retry::retry;
+    pub use retry_if::retry_if;
     pub use with_ctx::with_ctx;
 }
 
 mod then;
 mod map;
 mod and_then;
+mod batch;
+mod cache;
+mod chain;
+mod hooks;
 mod map_err;
+mod err_into;
+mod context;
+mod any_error;
 mod or_else;
 mod abort;
 mod try_abort;
@@ -113,19 +145,33 @@ mod branch4;
 mod loop_fn;
 mod repeat;
 mod retry;
+mod retry_if;
 mod result;
 mod ok;
 mod err;
 mod lazy;
 mod join_all;
 mod with_ctx;
+mod memo;
+mod named;
+mod plan;
+mod propagation;
+mod transactional;
 
 pub use abort::*;
+pub use backend::TxBackend;
+pub use batch::*;
 pub use and_then::*;
+pub use any_error::*;
 pub use branch::*;
 pub use branch3::*;
 pub use branch4::*;
+pub use cache::*;
+pub use chain::*;
+pub use context::*;
 pub use err::*;
+pub use err_into::*;
+pub use hooks::*;
 pub use join::*;
 pub use join3::*;
 pub use join4::*;
@@ -134,23 +180,33 @@ pub use lazy::*;
 pub use loop_fn::*;
 pub use map::*;
 pub use map_err::*;
+pub use memo::*;
+pub use named::*;
 pub use ok::*;
 pub use or_else::*;
+pub use plan::*;
+pub use propagation::*;
 pub use recover::*;
 pub use repeat::*;
 pub use result::*;
 pub use retry::*;
+pub use retry_if::*;
 pub use then::*;
 pub use try_abort::*;
 pub use try_recover::*;
 pub use with_ctx::*;
 
+use std::borrow::Cow;
+
 /// An abstract transaction. Transactions sharing the same `Ctx` can be
 /// composed with combinators. When the transaction return an error, it means
 /// the transaction is failed. Some runners may abort the transaction and the
 /// other may retry the computation. Thus all the computation should be
 /// idempotent (of cause, except operations using context). Note that this
 /// transaction is not executed until it is `run`.
+///
+/// Combinators are `Send`/`Sync` whenever the transactions and closures
+/// they hold are, regardless of `Ctx`, `Item` and `Err`.
 #[must_use]
 pub trait Transaction {
     /// The contxt type (i.e. transaction type) of the transaction
@@ -164,14 +220,57 @@ pub trait Transaction {
     /// user by hand.
     fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err>;
 
+    /// Collect the `fetch`es of the transaction into the batch, so that a
+    /// join loads the `fetch`es of its transactions at once. By default
+    /// nothing is collected, and combinators forward it to the transactions
+    /// run first.
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        let _ = batch;
+    }
+
+    /// Run the transaction with the values loaded by the batch, which the
+    /// transaction is `prefetch`ed into. By default this is `run`.
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
+        let _ = batch;
+        self.run(ctx)
+    }
+
+    /// Describe the structure of the transaction without running it.
+    /// See `Plan` for details.
+    fn describe(&self) -> Plan {
+        Plan::leaf(plan::default_label::<Self>())
+    }
+
+    /// The propagation set by `required`, `requires_new`, `nested` or
+    /// `never`. Runners honour it.
+    fn propagation(&self) -> Option<Propagation> {
+        None
+    }
+
     /// Box the transaction
-    fn boxed<'a>(self) -> Box<Transaction<Ctx = Self::Ctx, Item = Self::Item, Err = Self::Err> + 'a>
+    fn boxed<'a>(self) -> BoxTx<'a, Self::Ctx, Self::Item, Self::Err>
     where
         Self: Sized + 'a,
     {
         Box::new(self)
     }
 
+    /// Box the transaction keeping it `Send`
+    fn boxed_send<'a>(self) -> SendBoxTx<'a, Self::Ctx, Self::Item, Self::Err>
+    where
+        Self: Sized + Send + 'a,
+    {
+        Box::new(self)
+    }
+
+    /// Box the transaction keeping it `Send` and `Sync`
+    fn boxed_sync<'a>(self) -> SyncBoxTx<'a, Self::Ctx, Self::Item, Self::Err>
+    where
+        Self: Sized + Send + Sync + 'a,
+    {
+        Box::new(self)
+    }
+
     /// Take the previous result of computation and do another computation
     fn then<F, B, Tx2>(self, f: F) -> Then<Self, F, Tx2>
     where
@@ -213,6 +312,90 @@ pub trait Transaction {
         map_err(self, f)
     }
 
+    /// Convert the error value into another type using `Into`
+    fn err_into<E>(self) -> ErrInto<Self, E>
+    where
+        Self::Err: Into<E>,
+        Self: Sized,
+    {
+        err_into(self)
+    }
+
+    /// Name the transaction. The name is shown in `describe`
+    fn named<N>(self, name: N) -> Named<Self>
+    where
+        N: Into<Cow<'static, str>>,
+        Self: Sized,
+    {
+        named(self, name)
+    }
+
+    /// Cache the result in the context for the rest of the run, so that
+    /// running this transaction again returns the same value.
+    /// The result is kept for this transaction value only; use `memo_by` to
+    /// share it with transactions built elsewhere.
+    fn memo(self) -> Memo<Self>
+    where
+        Self: Sized,
+    {
+        memo(self)
+    }
+
+    /// Cache the result in the context by the key for the rest of the run.
+    /// Transactions with the same key and item type share the result, so the
+    /// key should identify the query, e.g. `("user", id)`.
+    fn memo_by<K>(self, key: K) -> MemoBy<Self, K>
+    where
+        Self: Sized,
+    {
+        memo_by(self, key)
+    }
+
+    /// Join the current transaction, or begin one if there is none
+    fn required(self) -> Propagate<Self>
+    where
+        Self: Sized,
+    {
+        propagate(self, Propagation::Required)
+    }
+
+    /// Run in a transaction of its own, committed separately on a new
+    /// connection even when run inside another transaction
+    fn requires_new(self) -> Propagate<Self>
+    where
+        Self: Sized,
+    {
+        propagate(self, Propagation::RequiresNew)
+    }
+
+    /// Run in a savepoint of the current transaction, or begin one if there is
+    /// none. On error only the changes of this transaction are rolled back.
+    fn nested(self) -> Propagate<Self>
+    where
+        Self: Sized,
+    {
+        propagate(self, Propagation::Nested)
+    }
+
+    /// Run outside of transactions. Fails with
+    /// `PropagationError::InTransaction` if run inside one.
+    fn never(self) -> Propagate<Self>
+    where
+        Self: Sized,
+    {
+        propagate(self, Propagation::Never)
+    }
+
+    /// Convert the error value into `AnyTxError` attaching the given context
+    fn context<C>(self, c: C) -> Context<Self>
+    where
+        Self::Err: Into<AnyTxError>,
+        C: Into<Cow<'static, str>>,
+        Self: Sized,
+    {
+        context(self, c)
+    }
+
 
     /// Take the previous error value of computation and do another computation.
     /// This may be used falling back
@@ -292,6 +475,18 @@ pub trait Transaction {
         join4(self, b, c, d)
     }
 
+    /// join 2 indepndant transactions with different error types, converting
+    /// both errors into `E`
+    fn join_err_into<E, B>(self, b: B) -> Join<ErrInto<Self, E>, ErrInto<B::Tx, E>>
+    where
+        B: IntoTransaction<Self::Ctx>,
+        Self::Err: Into<E>,
+        B::Err: Into<E>,
+        Self: Sized,
+    {
+        join(err_into(self), err_into(b))
+    }
+
     /// branch builder
     fn branch(self) -> BranchBuilder<Self>
     where
@@ -317,6 +512,39 @@ pub trait Transaction {
     }
 }
 
+/// A shorthand of `Transaction<Ctx = Ctx, Item = T, Err = E>`, mainly for
+/// return types.
+///
+/// ```
+/// # extern crate transaction;
+/// # use transaction::prelude::*;
+/// # struct FooConnection;
+/// fn answer() -> impl TxFor<FooConnection, i32, ()> {
+///     ok(42)
+/// }
+/// # fn main() { assert_eq!(answer().run(&mut FooConnection), Ok(42)); }
+/// ```
+pub trait TxFor<Ctx, T, E>: Transaction<Ctx = Ctx, Item = T, Err = E> {}
+
+impl<Ctx, T, E, Tx> TxFor<Ctx, T, E> for Tx
+where
+    Tx: ?Sized + Transaction<Ctx = Ctx, Item = T, Err = E>,
+{
+}
+
+/// A boxed transaction, returned by `boxed`
+pub type BoxTx<'a, Ctx, T, E> = Box<dyn Transaction<Ctx = Ctx, Item = T, Err = E> + 'a>;
+
+/// A boxed transaction that can be sent to another thread, returned by
+/// `boxed_send`
+pub type SendBoxTx<'a, Ctx, T, E> = Box<dyn Transaction<Ctx = Ctx, Item = T, Err = E> + Send + 'a>;
+
+/// A boxed transaction that can be shared between threads, returned by
+/// `boxed_sync`
+pub type SyncBoxTx<'a, Ctx, T, E> = Box<
+    dyn Transaction<Ctx = Ctx, Item = T, Err = E> + Send + Sync + 'a,
+>;
+
 /// types than can be converted into transaction
 pub trait IntoTransaction<Ctx> {
     type Tx: Transaction<Ctx = Ctx, Item = Self::Item, Err = Self::Err>;
@@ -420,13 +648,17 @@ where
     }
 }
 
-impl<Ctx, T, E> Transaction for Fn(&mut Ctx) -> Result<T, E> {
+impl<Ctx, T, E> Transaction for dyn Fn(&mut Ctx) -> Result<T, E> {
     type Ctx = Ctx;
     type Item = T;
     type Err = E;
     fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
         self(ctx)
     }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("fn")
+    }
 }
 
 
@@ -440,9 +672,25 @@ where
     fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
         (**self).run(ctx)
     }
+
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        (**self).prefetch(batch)
+    }
+
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
+        (**self).run_prefetched(ctx, batch)
+    }
+
+    fn describe(&self) -> Plan {
+        (**self).describe()
+    }
+
+    fn propagation(&self) -> Option<Propagation> {
+        (**self).propagation()
+    }
 }
 
-impl<'a, T> Transaction for &'a T
+impl<T> Transaction for &T
 where
     T: ?Sized + Transaction,
 {
@@ -452,4 +700,20 @@ where
     fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
         (**self).run(ctx)
     }
+
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        (**self).prefetch(batch)
+    }
+
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
+        (**self).run_prefetched(ctx, batch)
+    }
+
+    fn describe(&self) -> Plan {
+        (**self).describe()
+    }
+
+    fn propagation(&self) -> Option<Propagation> {
+        (**self).propagation()
+    }
 }
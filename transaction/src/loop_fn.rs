@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use {IntoTransaction, Plan, Transaction};
 
 pub fn loop_fn<Ctx, S, T, F, A>(initial_state: S, f: F) -> LoopFn<Ctx, F, A>
 where
@@ -20,7 +20,7 @@ where
 pub struct LoopFn<Ctx, F, A: IntoTransaction<Ctx>> {
     tx: A::Tx,
     f: F,
-    _phantom: PhantomData<(Ctx)>,
+    _phantom: PhantomData<fn() -> Ctx>,
 }
 
 /// The status of a `loop_fn` loop.
@@ -51,4 +51,8 @@ where
             ret = f(s).into_transaction().run(ctx)?;
         }
     }
+
+    fn describe(&self) -> Plan {
+        Plan::node("loop_fn", vec![self.tx.describe(), Plan::closure()])
+    }
 }
@@ -1,4 +1,4 @@
-use {IntoTransaction, Transaction};
+use {Batch, IntoTransaction, Plan, Transaction};
 
 pub fn map<Ctx, A, F, B>(a: A, f: F) -> Map<A::Tx, F>
 where
@@ -33,4 +33,16 @@ where
         let &Map { ref tx, ref f } = self;
         tx.run(ctx).map(f)
     }
+
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        self.tx.prefetch(batch)
+    }
+
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
+        self.tx.run_prefetched(ctx, batch).map(&self.f)
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::node("map", vec![self.tx.describe()])
+    }
 }
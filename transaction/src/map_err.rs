@@ -1,4 +1,4 @@
-use {IntoTransaction, Transaction};
+use {IntoTransaction, Plan, Transaction};
 
 pub fn map_err<Ctx, A, F, B>(a: A, f: F) -> MapErr<A::Tx, F>
 where
@@ -33,4 +33,8 @@ where
         let &MapErr { ref tx, ref f } = self;
         tx.run(ctx).map_err(f)
     }
+
+    fn describe(&self) -> Plan {
+        Plan::node("map_err", vec![self.tx.describe()])
+    }
 }
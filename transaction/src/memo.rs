@@ -0,0 +1,209 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use {Batch, IntoTransaction, Plan, Transaction};
+
+// the identities of `Memo`s
+static MEMO_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The cache of `memo` and `memo_by` held by a context.
+/// It should live as long as one run, and be cleared when the transaction
+/// (or a savepoint) is rolled back, as the cached values may be gone.
+/// Contexts should also clear it before writes, which may change the cached
+/// values. `transaction-diesel` does it on `with_conn` and the other writing
+/// transactions.
+#[derive(Debug, Default)]
+pub struct MemoCache {
+    // `HashMap<K, V>` for each `(K, V)`
+    entries: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl MemoCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        MemoCache::default()
+    }
+
+    /// Forget all the cached values
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn get<K, V>(&self, key: &K) -> Option<&V>
+    where
+        K: Eq + Hash + 'static,
+        V: 'static,
+    {
+        self.entries
+            .get(&TypeId::of::<(K, V)>())
+            .and_then(|map| map.downcast_ref::<HashMap<K, V>>())
+            .and_then(|map| map.get(key))
+    }
+
+    fn insert<K, V>(&mut self, key: K, value: V)
+    where
+        K: Eq + Hash + 'static,
+        V: 'static,
+    {
+        self.entries
+            .entry(TypeId::of::<(K, V)>())
+            .or_insert_with(|| Box::new(HashMap::<K, V>::new()))
+            .downcast_mut::<HashMap<K, V>>()
+            .expect("the entry of (K, V) is HashMap<K, V>")
+            .insert(key, value);
+    }
+}
+
+/// Contexts that can hold the results of `memo` and `memo_by`
+///
+/// ```
+/// # extern crate transaction;
+/// # use std::cell::Cell;
+/// # use transaction::prelude::*;
+/// # use transaction::{MemoCache, MemoCtx};
+/// struct Ctx {
+///     memo: MemoCache,
+/// }
+///
+/// impl MemoCtx for Ctx {
+///     fn memo_cache(&mut self) -> &mut MemoCache {
+///         &mut self.memo
+///     }
+/// }
+///
+/// # fn main() {
+/// let queries = Cell::new(0);
+/// let queries = &queries;
+/// let find_user = |id: i64| {
+///     with_ctx(move |_: &mut Ctx| -> Result<String, ()> {
+///         queries.set(queries.get() + 1);
+///         Ok(format!("user{}", id))
+///     }).memo_by(("user", id))
+/// };
+/// let tx = find_user(1).join3(find_user(2), find_user(1));
+/// let mut ctx = Ctx { memo: MemoCache::new() };
+/// assert_eq!(tx.run(&mut ctx).unwrap().2, "user1");
+/// assert_eq!(queries.get(), 2);
+/// # }
+/// ```
+pub trait MemoCtx {
+    /// The cache of the current run
+    fn memo_cache(&mut self) -> &mut MemoCache;
+}
+
+pub fn memo<Ctx, A>(a: A) -> Memo<A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+{
+    Memo {
+        tx: a.into_transaction(),
+        id: MemoId(MEMO_ID.fetch_add(1, Ordering::Relaxed)),
+    }
+}
+
+/// The result of `memo`
+#[derive(Debug)]
+#[must_use]
+pub struct Memo<Tx> {
+    tx: Tx,
+    id: MemoId,
+}
+
+// the key of the result of a `Memo` in `MemoCache`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct MemoId(usize);
+
+impl<Tx> Transaction for Memo<Tx>
+where
+    Tx: Transaction,
+    Tx::Ctx: MemoCtx,
+    Tx::Item: Clone + 'static,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        if let Some(item) = ctx.memo_cache().get::<MemoId, Tx::Item>(&self.id) {
+            return Ok(item.clone());
+        }
+        let item = self.tx.run(ctx)?;
+        ctx.memo_cache().insert(self.id.clone(), item.clone());
+        Ok(item)
+    }
+
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        self.tx.prefetch(batch)
+    }
+
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
+        if let Some(item) = ctx.memo_cache().get::<MemoId, Tx::Item>(&self.id) {
+            return Ok(item.clone());
+        }
+        let item = self.tx.run_prefetched(ctx, batch)?;
+        ctx.memo_cache().insert(self.id.clone(), item.clone());
+        Ok(item)
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::node("memo", vec![self.tx.describe()])
+    }
+}
+
+pub fn memo_by<Ctx, A, K>(a: A, key: K) -> MemoBy<A::Tx, K>
+where
+    A: IntoTransaction<Ctx>,
+{
+    MemoBy {
+        tx: a.into_transaction(),
+        key: key,
+    }
+}
+
+/// The result of `memo_by`
+#[derive(Debug)]
+#[must_use]
+pub struct MemoBy<Tx, K> {
+    tx: Tx,
+    key: K,
+}
+
+impl<Tx, K> Transaction for MemoBy<Tx, K>
+where
+    Tx: Transaction,
+    Tx::Ctx: MemoCtx,
+    Tx::Item: Clone + 'static,
+    K: Eq + Hash + Clone + 'static,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        if let Some(item) = ctx.memo_cache().get::<K, Tx::Item>(&self.key) {
+            return Ok(item.clone());
+        }
+        let item = self.tx.run(ctx)?;
+        ctx.memo_cache().insert(self.key.clone(), item.clone());
+        Ok(item)
+    }
+
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        self.tx.prefetch(batch)
+    }
+
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
+        if let Some(item) = ctx.memo_cache().get::<K, Tx::Item>(&self.key) {
+            return Ok(item.clone());
+        }
+        let item = self.tx.run_prefetched(ctx, batch)?;
+        ctx.memo_cache().insert(self.key.clone(), item.clone());
+        Ok(item)
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::node("memo_by", vec![self.tx.describe()])
+    }
+}
@@ -0,0 +1,55 @@
+use std::borrow::Cow;
+
+use {Batch, IntoTransaction, Plan, Transaction};
+
+pub fn named<Ctx, A, N>(a: A, name: N) -> Named<A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+    N: Into<Cow<'static, str>>,
+{
+    Named {
+        tx: a.into_transaction(),
+        name: name.into(),
+    }
+}
+
+
+/// The result of `named`
+#[derive(Debug)]
+#[must_use]
+pub struct Named<Tx> {
+    tx: Tx,
+    name: Cow<'static, str>,
+}
+
+impl<Tx> Named<Tx> {
+    /// The name of the transaction
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<Tx> Transaction for Named<Tx>
+where
+    Tx: Transaction,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        self.tx.run(ctx)
+    }
+
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        self.tx.prefetch(batch)
+    }
+
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
+        self.tx.run_prefetched(ctx, batch)
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::node(format!("named: {}", self.name), vec![self.tx.describe()])
+    }
+}
@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use Transaction;
+use {Plan, Transaction};
 
 /// make a successful transaction value.
 pub fn ok<Ctx, T, E>(t: T) -> TxOk<Ctx, T, E> {
@@ -15,7 +15,7 @@ pub fn ok<Ctx, T, E>(t: T) -> TxOk<Ctx, T, E> {
 #[must_use]
 pub struct TxOk<Ctx, T, E> {
     ok: T,
-    _phantom: PhantomData<(Ctx, E)>,
+    _phantom: PhantomData<fn() -> (Ctx, E)>,
 }
 
 impl<Ctx, T, E> Transaction for TxOk<Ctx, T, E>
@@ -28,4 +28,8 @@ where
     fn run(&self, _ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
         Ok(self.ok.clone())
     }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("ok")
+    }
 }
@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use {IntoTransaction, Plan, Transaction};
 
 
 pub fn or_else<Ctx, A, F, B>(a: A, f: F) -> OrElse<A::Tx, F, B>
@@ -23,7 +23,7 @@ where
 pub struct OrElse<Tx1, F, Tx2> {
     tx: Tx1,
     f: F,
-    _phantom: PhantomData<Tx2>,
+    _phantom: PhantomData<fn() -> Tx2>,
 }
 
 impl<Tx, Tx2, F> Transaction for OrElse<Tx, F, Tx2>
@@ -46,4 +46,8 @@ where
             |item| f(item).into_transaction().run(ctx),
         )
     }
+
+    fn describe(&self) -> Plan {
+        Plan::node("or_else", vec![self.tx.describe(), Plan::closure()])
+    }
 }
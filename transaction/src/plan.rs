@@ -0,0 +1,112 @@
+use std::borrow::Cow;
+use std::fmt;
+
+/// The structure of a transaction, returned by `Transaction::describe`.
+///
+/// Each combinator is a node and its sub transactions are the children.
+/// Transactions made by closures at run time (e.g. the result of `f` of
+/// `and_then`) are not known before running, so they are shown as `<fn>`.
+/// Render it as an indented text by `Display` or as a graphviz graph by
+/// `to_dot`.
+///
+/// ```
+/// # extern crate transaction;
+/// # use transaction::prelude::*;
+/// # fn main() {
+/// let tx = with_ctx(|_: &mut ()| Ok::<_, ()>(1))
+///     .named("find")
+///     .join(ok(2))
+///     .and_then(|(a, b)| ok(a + b));
+/// assert_eq!(
+///     tx.describe().to_string(),
+///     "and_then\n  join\n    named: find\n      with_ctx\n    ok\n  <fn>\n"
+/// );
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    label: Cow<'static, str>,
+    children: Vec<Plan>,
+}
+
+impl Plan {
+    /// Create a node without children
+    pub fn leaf<L>(label: L) -> Self
+    where
+        L: Into<Cow<'static, str>>,
+    {
+        Plan::node(label, Vec::new())
+    }
+
+    /// Create a node
+    pub fn node<L>(label: L, children: Vec<Plan>) -> Self
+    where
+        L: Into<Cow<'static, str>>,
+    {
+        Plan {
+            label: label.into(),
+            children: children,
+        }
+    }
+
+    /// A transaction that is made by a closure at run time
+    pub fn closure() -> Self {
+        Plan::leaf("<fn>")
+    }
+
+    /// The label of the node
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// The children of the node
+    pub fn children(&self) -> &[Plan] {
+        &self.children
+    }
+
+    /// Render the plan in the graphviz DOT language
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph plan {\n");
+        let mut id = 0;
+        self.write_dot(&mut out, &mut id);
+        out.push_str("}\n");
+        out
+    }
+
+    fn write_dot(&self, out: &mut String, id: &mut usize) -> usize {
+        let me = *id;
+        *id += 1;
+        out.push_str(&format!(
+            "    n{} [label=\"{}\"];\n",
+            me,
+            self.label.replace('\\', "\\\\").replace('"', "\\\"")
+        ));
+        for child in &self.children {
+            let child_id = child.write_dot(out, id);
+            out.push_str(&format!("    n{} -> n{};\n", me, child_id));
+        }
+        me
+    }
+
+    fn write_text(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        writeln!(f, "{:width$}{}", "", self.label, width = depth * 2)?;
+        for child in &self.children {
+            child.write_text(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_text(f, 0)
+    }
+}
+
+// the default label of transactions: the type name without the path and
+// the type parameters
+pub(crate) fn default_label<T: ?Sized>() -> &'static str {
+    let name = ::std::any::type_name::<T>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
@@ -0,0 +1,166 @@
+use std::error::Error;
+use std::fmt;
+
+use backend::{self, TxBackend};
+use {Batch, IntoTransaction, Plan, Transaction};
+
+/// How a transaction joins the transaction it is run in.
+/// Set by `required`, `requires_new`, `nested` and `never`, and honoured
+/// when the context is a `TxBackend`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Propagation {
+    /// Join the current transaction, or begin one if there is none
+    Required,
+    /// Always run in a transaction of its own, committed separately on a new
+    /// connection of the backend
+    RequiresNew,
+    /// Run in a savepoint of the current transaction, or begin one if there
+    /// is none
+    Nested,
+    /// Run outside of transactions. It is an error to run it inside one
+    Never,
+}
+
+/// The error when the propagation of a transaction cannot be honoured
+#[derive(Debug)]
+pub enum PropagationError {
+    /// A `never` transaction is run inside a transaction
+    InTransaction,
+    /// The backend cannot open a new connection for `requires_new`
+    Unsupported,
+    /// Failed to open a new connection for `requires_new`
+    Connect(Box<dyn Error + Send + Sync>),
+}
+
+impl fmt::Display for PropagationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PropagationError::InTransaction => {
+                write!(f, "a `never` transaction is run inside a transaction")
+            }
+            PropagationError::Unsupported => {
+                write!(f, "the backend cannot open a new connection")
+            }
+            PropagationError::Connect(ref e) => write!(f, "could not open a new connection: {}", e),
+        }
+    }
+}
+
+impl Error for PropagationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PropagationError::Connect(ref e) => Some(&**e),
+            _ => None,
+        }
+    }
+}
+
+pub fn propagate<Ctx, A>(a: A, propagation: Propagation) -> Propagate<A::Tx>
+where
+    A: IntoTransaction<Ctx>,
+{
+    Propagate {
+        tx: a.into_transaction(),
+        propagation: propagation,
+    }
+}
+
+/// The result of `required`, `requires_new`, `nested` and `never`
+#[derive(Debug)]
+#[must_use]
+pub struct Propagate<Tx> {
+    tx: Tx,
+    propagation: Propagation,
+}
+
+impl<Tx> Propagate<Tx> {
+    // run `tx`, which is `self.tx` with or without a batch, as propagated
+    fn run_as_propagated<B, T>(&self, ctx: &mut B, tx: T) -> Result<T::Item, T::Err>
+    where
+        B: TxBackend<Ctx = B>,
+        T: Transaction<Ctx = B>,
+        T::Err: From<B::Error> + From<PropagationError>,
+    {
+        let in_transaction = ctx.in_transaction()?;
+        match (self.propagation, in_transaction) {
+            (Propagation::Required, true) => tx.run(ctx),
+            (Propagation::Nested, true) => backend::run_in_savepoint(ctx, &tx),
+            (Propagation::RequiresNew, true) => {
+                let mut new = ctx.new_backend()?;
+                backend::run_in_transaction(&mut new, &tx)
+            }
+            (Propagation::Never, true) => Err(PropagationError::InTransaction.into()),
+            (Propagation::Never, false) => tx.run(ctx),
+            (_, false) => backend::run_in_transaction(ctx, &tx),
+        }
+    }
+}
+
+impl<Tx, B> Transaction for Propagate<Tx>
+where
+    Tx: Transaction<Ctx = B>,
+    B: TxBackend<Ctx = B>,
+    Tx::Err: From<B::Error> + From<PropagationError>,
+{
+    type Ctx = B;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        self.run_as_propagated(ctx, &self.tx)
+    }
+
+    // only the transactions on the same connection can use the batch
+    fn prefetch<'b>(&'b self, batch: &mut Batch<'b, Self::Ctx>) {
+        match self.propagation {
+            Propagation::Required | Propagation::Nested => self.tx.prefetch(batch),
+            Propagation::RequiresNew | Propagation::Never => (),
+        }
+    }
+
+    fn run_prefetched(&self, ctx: &mut Self::Ctx, batch: &Batch<Self::Ctx>) -> Result<Self::Item, Self::Err> {
+        match self.propagation {
+            Propagation::Required | Propagation::Nested => {
+                self.run_as_propagated(ctx, Prefetched {
+                    tx: &self.tx,
+                    batch: batch,
+                })
+            }
+            Propagation::RequiresNew | Propagation::Never => self.run(ctx),
+        }
+    }
+
+    fn describe(&self) -> Plan {
+        let label = match self.propagation {
+            Propagation::Required => "required",
+            Propagation::RequiresNew => "requires_new",
+            Propagation::Nested => "nested",
+            Propagation::Never => "never",
+        };
+        Plan::node(label, vec![self.tx.describe()])
+    }
+
+    fn propagation(&self) -> Option<Propagation> {
+        Some(self.propagation)
+    }
+}
+
+// run the transaction with the values loaded by the batch
+struct Prefetched<'a, 'b: 'a, Tx: Transaction + 'a> {
+    tx: &'a Tx,
+    batch: &'a Batch<'b, Tx::Ctx>,
+}
+
+impl<'a, 'b, Tx: Transaction> Transaction for Prefetched<'a, 'b, Tx> {
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        self.tx.run_prefetched(ctx, self.batch)
+    }
+
+    fn describe(&self) -> Plan {
+        self.tx.describe()
+    }
+}
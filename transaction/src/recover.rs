@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use {IntoTransaction, Plan, Transaction};
 
 pub fn recover<Ctx, A, T, F>(a: A, f: F) -> Recover<A::Tx, T, F>
 where
@@ -20,7 +20,7 @@ where
 pub struct Recover<Tx, T, F> {
     tx: Tx,
     f: F,
-    _phantom: PhantomData<T>,
+    _phantom: PhantomData<fn() -> T>,
 }
 
 impl<Tx, F, T> Transaction for Recover<Tx, T, F>
@@ -39,4 +39,8 @@ where
             Err(e) => Ok(f(e)),
         }
     }
+
+    fn describe(&self) -> Plan {
+        Plan::node("recover", vec![self.tx.describe()])
+    }
 }
@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use {IntoTransaction, Plan, Transaction};
 
 pub fn repeat<Ctx, F, Tx>(n: usize, f: F) -> Repeat<Ctx, F, Tx>
 where
@@ -20,7 +20,7 @@ where
 pub struct Repeat<Ctx, F, Tx> {
     n: usize,
     f: F,
-    _phantom: PhantomData<(Tx, Ctx)>,
+    _phantom: PhantomData<fn() -> (Tx, Ctx)>,
 }
 
 impl<Ctx, F, Tx> Transaction for Repeat<Ctx, F, Tx>
@@ -40,4 +40,8 @@ where
         }
         Ok(ret)
     }
+
+    fn describe(&self) -> Plan {
+        Plan::node(format!("repeat({})", self.n), vec![Plan::closure()])
+    }
 }
@@ -1,13 +1,13 @@
 use std::marker::PhantomData;
 
-use Transaction;
+use {Plan, Transaction};
 
 /// The result of `result`
 #[derive(Debug)]
 #[must_use]
 pub struct TxResult<Ctx, T, E> {
     r: Result<T, E>,
-    _phantom: PhantomData<Ctx>,
+    _phantom: PhantomData<fn() -> Ctx>,
 }
 
 /// Take a result and make a leaf transaction value.
@@ -29,4 +29,8 @@ where
     fn run(&self, _ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
         self.r.clone()
     }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("result")
+    }
 }
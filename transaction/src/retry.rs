@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use {IntoTransaction, Plan, Transaction};
 
 
 
@@ -22,7 +22,7 @@ where
 pub struct Retry<Ctx, F, Tx> {
     n: usize,
     f: F,
-    _phantom: PhantomData<(Tx, Ctx)>,
+    _phantom: PhantomData<fn() -> (Tx, Ctx)>,
 }
 
 impl<Ctx, F, Tx> Transaction for Retry<Ctx, F, Tx>
@@ -45,4 +45,8 @@ where
         }
         Err(ret)
     }
+
+    fn describe(&self) -> Plan {
+        Plan::node(format!("retry({})", self.n), vec![Plan::closure()])
+    }
 }
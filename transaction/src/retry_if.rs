@@ -0,0 +1,58 @@
+use std::marker::PhantomData;
+
+use {IntoTransaction, Plan, Transaction};
+
+/// Retry the transaction up to `n` times while the error satisfies `p`.
+pub fn retry_if<Ctx, P, F, Tx>(n: usize, p: P, f: F) -> RetryIf<Ctx, P, F, Tx>
+where
+    Tx: IntoTransaction<Ctx>,
+    P: Fn(&Tx::Err) -> bool,
+    F: Fn(usize) -> Tx,
+{
+    RetryIf {
+        n: n,
+        p: p,
+        f: f,
+        _phantom: PhantomData,
+    }
+}
+
+/// The result of `retry_if`
+#[derive(Debug)]
+#[must_use]
+pub struct RetryIf<Ctx, P, F, Tx> {
+    n: usize,
+    p: P,
+    f: F,
+    _phantom: PhantomData<fn() -> (Tx, Ctx)>,
+}
+
+impl<Ctx, P, F, Tx> Transaction for RetryIf<Ctx, P, F, Tx>
+where
+    P: Fn(&Tx::Err) -> bool,
+    F: Fn(usize) -> Tx,
+    Tx: IntoTransaction<Ctx>,
+{
+    type Ctx = Ctx;
+    type Item = Tx::Item;
+    type Err = Vec<Tx::Err>;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        let mut ret = Vec::new();
+        for i in 0..self.n {
+            let t = match (self.f)(i).into_transaction().run(ctx) {
+                Ok(t) => return Ok(t),
+                Err(e) => e,
+            };
+            let retry = (self.p)(&t);
+            ret.push(t);
+            if !retry {
+                break;
+            }
+        }
+        Err(ret)
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::node(format!("retry_if({})", self.n), vec![Plan::closure()])
+    }
+}
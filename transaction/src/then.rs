@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use {IntoTransaction, Plan, Transaction};
 
 pub fn then<Ctx, A, F, B, Tx2>(a: A, f: F) -> Then<A::Tx, F, Tx2>
 where
@@ -21,7 +21,7 @@ where
 pub struct Then<Tx1, F, Tx2> {
     tx: Tx1,
     f: F,
-    _phantom: PhantomData<Tx2>,
+    _phantom: PhantomData<fn() -> Tx2>,
 }
 
 impl<Tx, Tx2, F> Transaction for Then<Tx, F, Tx2>
@@ -38,4 +38,8 @@ where
         let &Then { ref tx, ref f, .. } = self;
         f(tx.run(ctx)).into_transaction().run(ctx)
     }
+
+    fn describe(&self) -> Plan {
+        Plan::node("then", vec![self.tx.describe(), Plan::closure()])
+    }
 }
@@ -0,0 +1,193 @@
+// the runtime part of `#[transactional]`. The macro rewrites the body of the
+// function into a transaction built from the items here and the combinators.
+use std::marker::PhantomData;
+
+use {err_into, ErrInto, Loop, Plan, Transaction};
+
+/// Run a transaction, or pass through a `Result`, under the given context.
+pub trait Bind<Ctx> {
+    type Item;
+    type Err;
+    fn bind(self, ctx: &mut Ctx) -> Result<Self::Item, Self::Err>;
+}
+
+impl<Ctx, Tx> Bind<Ctx> for Tx
+where
+    Tx: Transaction<Ctx = Ctx>,
+{
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+    fn bind(self, ctx: &mut Ctx) -> Result<Self::Item, Self::Err> {
+        self.run(ctx)
+    }
+}
+
+impl<Ctx, T, E> Bind<Ctx> for Result<T, E> {
+    type Item = T;
+    type Err = E;
+    fn bind(self, _ctx: &mut Ctx) -> Result<T, E> {
+        self
+    }
+}
+
+/// The operand of `?`, a transaction or a `Result`, as a transaction
+/// failing with `E`.
+pub trait Step<Ctx, E> {
+    type Tx: Transaction<Ctx = Ctx, Err = E>;
+    fn step(self) -> Self::Tx;
+}
+
+impl<Ctx, E, Tx> Step<Ctx, E> for Tx
+where
+    Tx: Transaction<Ctx = Ctx>,
+    Tx::Err: Into<E>,
+{
+    type Tx = ErrInto<Tx, E>;
+    fn step(self) -> Self::Tx {
+        err_into(self)
+    }
+}
+
+impl<Ctx, E, T, E1> Step<Ctx, E> for Result<T, E1>
+where
+    T: Clone,
+    E: Clone,
+    E1: Into<E>,
+{
+    type Tx = Ready<Ctx, T, E>;
+    fn step(self) -> Self::Tx {
+        ready(self.map_err(Into::into))
+    }
+}
+
+pub fn step<Ctx, E, S>(s: S) -> S::Tx
+where
+    S: Step<Ctx, E>,
+{
+    s.step()
+}
+
+/// A transaction returning an already computed result, cloned at each run
+/// like `result`.
+#[must_use]
+pub struct Ready<Ctx, T, E> {
+    r: Result<T, E>,
+    _phantom: PhantomData<fn() -> Ctx>,
+}
+
+pub fn ready<Ctx, T, E>(r: Result<T, E>) -> Ready<Ctx, T, E> {
+    Ready {
+        r: r,
+        _phantom: PhantomData,
+    }
+}
+
+impl<Ctx, T, E> Transaction for Ready<Ctx, T, E>
+where
+    T: Clone,
+    E: Clone,
+{
+    type Ctx = Ctx;
+    type Item = T;
+    type Err = E;
+    fn run(&self, _ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        self.r.clone()
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("result")
+    }
+}
+
+/// How a rewritten block is left. `Next` carries the value of the block,
+/// `Return` the value returned from the function.
+#[derive(Debug, Clone)]
+pub enum Flow<V, T> {
+    Next(V),
+    Return(T),
+    Break,
+    Continue,
+}
+
+impl<V, T> Flow<V, T> {
+    /// pass the exit to the enclosing block
+    pub fn exit<V2>(self) -> Flow<V2, T> {
+        match self {
+            Flow::Next(_) => unreachable!("not an exit"),
+            Flow::Return(t) => Flow::Return(t),
+            Flow::Break => Flow::Break,
+            Flow::Continue => Flow::Continue,
+        }
+    }
+}
+
+/// `return r` in a block, along with the mutable variables `m`
+pub fn ret<Ctx, M, V, T, E>(r: Result<T, E>, m: M) -> Ready<Ctx, (M, Flow<V, T>), E> {
+    ready(returned(r, m))
+}
+
+/// `return r` in a leaf, along with the mutable variables `m`
+pub fn returned<M, V, T, E>(r: Result<T, E>, m: M) -> Result<(M, Flow<V, T>), E> {
+    r.map(|t| (m, Flow::Return(t)))
+}
+
+/// exit from the function body
+pub fn exit<Ctx, V, T, E>(flow: Flow<V, T>) -> Ready<Ctx, T, E> {
+    match flow {
+        Flow::Return(t) => ready(Ok(t)),
+        _ => unreachable!("break or continue outside of a loop"),
+    }
+}
+
+/// the end of the function body without a value, i.e. after diverging loops
+pub fn unreachable<Ctx, T, E>() -> Ready<Ctx, T, E> {
+    unreachable!("the end of a function body is reached")
+}
+
+/// the state of `loop_fn` after an iteration of a loop body
+pub fn iterate<M, T>((m, flow): (M, Flow<(), T>)) -> Loop<M, (M, Flow<(), T>)> {
+    match flow {
+        Flow::Next(()) | Flow::Continue => Loop::Continue(m),
+        Flow::Break => Loop::Break((m, Flow::Next(()))),
+        Flow::Return(t) => Loop::Break((m, Flow::Return(t))),
+    }
+}
+
+/// Build the transaction of a function body at each run.
+pub fn defer<F, Tx>(name: &'static str, f: F) -> Defer<F>
+where
+    F: Fn() -> Tx,
+    Tx: Transaction,
+{
+    Defer { name: name, f: f }
+}
+
+/// The result of `defer`
+#[derive(Debug)]
+#[must_use]
+pub struct Defer<F> {
+    name: &'static str,
+    f: F,
+}
+
+impl<F, Tx> Transaction for Defer<F>
+where
+    F: Fn() -> Tx,
+    Tx: Transaction,
+{
+    type Ctx = Tx::Ctx;
+    type Item = Tx::Item;
+    type Err = Tx::Err;
+    fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
+        (self.f)().run(ctx)
+    }
+
+    fn describe(&self) -> Plan {
+        Plan::node(self.name, vec![Plan::closure()])
+    }
+}
+
+/// Make `impl Trait` capture the lifetime `'a` of the arguments.
+pub trait Captures<'a> {}
+
+impl<'a, T: ?Sized> Captures<'a> for T {}
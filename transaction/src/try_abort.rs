@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use {IntoTransaction, Plan, Transaction};
 
 pub fn try_abort<Ctx, A, F, B>(a: A, f: F) -> TryAbort<A::Tx, F, B>
 where
@@ -20,7 +20,7 @@ where
 pub struct TryAbort<Tx, F, B> {
     tx: Tx,
     f: F,
-    _phantom: PhantomData<B>,
+    _phantom: PhantomData<fn() -> B>,
 }
 
 impl<Tx, F, B> Transaction for TryAbort<Tx, F, B>
@@ -39,4 +39,8 @@ where
             Err(e) => Err(e),
         }
     }
+
+    fn describe(&self) -> Plan {
+        Plan::node("try_abort", vec![self.tx.describe()])
+    }
 }
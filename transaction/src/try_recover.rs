@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use {IntoTransaction, Transaction};
+use {IntoTransaction, Plan, Transaction};
 
 pub fn try_recover<Ctx, A, F, B>(a: A, f: F) -> TryRecover<A::Tx, F, B>
 where
@@ -21,7 +21,7 @@ where
 pub struct TryRecover<Tx, F, B> {
     tx: Tx,
     f: F,
-    _phantom: PhantomData<B>,
+    _phantom: PhantomData<fn() -> B>,
 }
 
 impl<Tx, F, B> Transaction for TryRecover<Tx, F, B>
@@ -40,4 +40,8 @@ where
             Err(e) => f(e),
         }
     }
+
+    fn describe(&self) -> Plan {
+        Plan::node("try_recover", vec![self.tx.describe()])
+    }
 }
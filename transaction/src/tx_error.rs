@@ -0,0 +1,43 @@
+/// Generate a sum type of errors and `From` conversions from each of them.
+/// The generated type can be used with `err_into` and `join_err_into` to
+/// compose transactions with different error types.
+///
+/// ```
+/// #[macro_use]
+/// extern crate transaction;
+///
+/// #[derive(Debug)]
+/// struct DbError;
+/// #[derive(Debug)]
+/// struct DomainError;
+///
+/// tx_error! {
+///     #[derive(Debug)]
+///     pub enum AppError {
+///         Db(DbError),
+///         Domain(DomainError),
+///     }
+/// }
+///
+/// # fn main() {
+/// let e: AppError = DbError.into();
+/// # let _ = e;
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tx_error {
+    ($(#[$attr:meta])* $vis:vis enum $name:ident { $($variant:ident($ty:ty)),* $(,)* }) => {
+        $(#[$attr])*
+        $vis enum $name {
+            $($variant($ty)),*
+        }
+
+        $(
+            impl ::std::convert::From<$ty> for $name {
+                fn from(e: $ty) -> Self {
+                    $name::$variant(e)
+                }
+            }
+        )*
+    };
+}
@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use Transaction;
+use {Plan, Transaction};
 
 
 /// Receive the context from the executing transaction and perform computation.
@@ -19,7 +19,7 @@ where
 #[must_use]
 pub struct WithCtx<Ctx, F> {
     f: F,
-    _phantom: PhantomData<Ctx>,
+    _phantom: PhantomData<fn() -> Ctx>,
 }
 
 impl<Ctx, T, E, F> Transaction for WithCtx<Ctx, F>
@@ -32,4 +32,8 @@ where
     fn run(&self, ctx: &mut Self::Ctx) -> Result<Self::Item, Self::Err> {
         (self.f)(ctx)
     }
+
+    fn describe(&self) -> Plan {
+        Plan::leaf("with_ctx")
+    }
 }
@@ -0,0 +1,193 @@
+extern crate transaction;
+
+use std::collections::HashMap;
+
+use transaction::prelude::*;
+use transaction::{backend, fetch, Batch, BatchLoader, MemoCache, MemoCtx, PropagationError, TxBackend};
+
+// the log of the loads and the other effects
+type Log = Vec<String>;
+
+struct Users;
+
+impl BatchLoader for Users {
+    type Ctx = Log;
+    type Key = i64;
+    type Value = String;
+    type Err = String;
+
+    fn load(&self, log: &mut Log, ids: &[i64]) -> Result<HashMap<i64, String>, String> {
+        let mut ids = ids.to_vec();
+        ids.sort();
+        log.push(format!("load {:?}", ids));
+        if ids.contains(&0) {
+            return Err("no user 0".to_string());
+        }
+        Ok(ids.iter().map(|id| (*id, format!("user{}", id))).collect())
+    }
+}
+
+// a backend logging the loads and the transactions
+#[derive(Default)]
+struct Db {
+    log: Log,
+    memo: MemoCache,
+    in_transaction: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct DbError(String);
+
+impl From<PropagationError> for DbError {
+    fn from(e: PropagationError) -> Self {
+        DbError(e.to_string())
+    }
+}
+
+impl MemoCtx for Db {
+    fn memo_cache(&mut self) -> &mut MemoCache {
+        &mut self.memo
+    }
+}
+
+impl TxBackend for Db {
+    type Ctx = Db;
+    type Error = DbError;
+
+    fn ctx(&mut self) -> &mut Db {
+        self
+    }
+
+    fn in_transaction(&mut self) -> Result<bool, DbError> {
+        Ok(self.in_transaction)
+    }
+
+    fn begin(&mut self) -> Result<(), DbError> {
+        self.in_transaction = true;
+        self.log.push("begin".to_string());
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), DbError> {
+        self.in_transaction = false;
+        self.log.push("commit".to_string());
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> Result<(), DbError> {
+        self.in_transaction = false;
+        self.log.push("rollback".to_string());
+        Ok(())
+    }
+
+    fn savepoint(&mut self) -> Result<(), DbError> {
+        self.log.push("savepoint".to_string());
+        Ok(())
+    }
+
+    fn release(&mut self) -> Result<(), DbError> {
+        self.log.push("release".to_string());
+        Ok(())
+    }
+
+    fn rollback_to_savepoint(&mut self) -> Result<(), DbError> {
+        self.log.push("rollback_to_savepoint".to_string());
+        Ok(())
+    }
+}
+
+struct DbUsers;
+
+impl BatchLoader for DbUsers {
+    type Ctx = Db;
+    type Key = i64;
+    type Value = String;
+    type Err = DbError;
+
+    fn load(&self, db: &mut Db, ids: &[i64]) -> Result<HashMap<i64, String>, DbError> {
+        Users.load(&mut db.log, ids).map_err(DbError)
+    }
+}
+
+fn push(line: &'static str) -> impl TxFor<Log, (), String> {
+    with_ctx(move |log: &mut Log| {
+        log.push(line.to_string());
+        Ok(())
+    })
+}
+
+#[test]
+fn join_all_keeps_the_order_without_fetches() {
+    let mut log = Vec::new();
+    let tx = join_all(vec![("a", "b"), ("c", "d")].into_iter().map(|(x, y)| {
+        push(x).and_then(move |_| push(y))
+    }));
+    tx.run(&mut log).unwrap();
+    assert_eq!(log, vec!["a", "b", "c", "d"]);
+}
+
+#[test]
+fn join_all_loads_fetches_at_once() {
+    let mut log = Vec::new();
+    let tx = join_all((1..4).map(|id| fetch(&Users, id).map(Option::unwrap)));
+    assert_eq!(tx.run(&mut log).unwrap(), vec!["user1", "user2", "user3"]);
+    assert_eq!(log, vec!["load [1, 2, 3]"]);
+}
+
+#[test]
+fn joins_load_fetches_at_once() {
+    let mut log = Vec::new();
+    let tx = fetch(&Users, 1)
+        .join(fetch(&Users, 2).and_then(|u| push("then").map(move |_| u.clone())))
+        .join3(
+            fetch(&Users, 3).err_into::<String>(),
+            fetch(&Users, 4).named("four").join(fetch(&Users, 5)),
+        );
+    let ((u1, u2), u3, (u4, u5)) = tx.run(&mut log).unwrap();
+    assert_eq!(
+        vec![u1, u2, u3, u4, u5],
+        (1..6).map(|id| Some(format!("user{}", id))).collect::<Vec<_>>()
+    );
+    assert_eq!(log, vec!["load [1, 2, 3, 4, 5]", "then"]);
+}
+
+#[test]
+fn fetches_report_the_error_of_the_load() {
+    let mut log = Vec::new();
+    let tx = fetch(&Users, 0).join(fetch(&Users, 1));
+    assert_eq!(tx.run(&mut log), Err("no user 0".to_string()));
+}
+
+#[test]
+fn every_fetch_reports_the_error_of_the_load() {
+    let mut log = Vec::new();
+    let (a, b) = (fetch(&Users, 0), fetch(&Users, 1));
+    let mut batch = Batch::new();
+    a.prefetch(&mut batch);
+    b.prefetch(&mut batch);
+    batch.load(&mut log);
+    assert_eq!(a.run_prefetched(&mut log, &batch), Err("no user 0".to_string()));
+    assert_eq!(b.run_prefetched(&mut log, &batch), Err("no user 0".to_string()));
+    assert_eq!(log, vec!["load [0, 1]"]);
+}
+
+#[test]
+fn memo_and_propagation_load_fetches_at_once() {
+    let mut db = Db::default();
+    let tx = fetch(&DbUsers, 1)
+        .memo()
+        .join3(
+            fetch(&DbUsers, 2).memo_by(2),
+            fetch(&DbUsers, 3).nested().join(fetch(&DbUsers, 4).required()),
+        )
+        .required();
+    let (u1, u2, (u3, u4)) = backend::run(&mut db, tx).unwrap();
+    assert_eq!(
+        vec![u1, u2, u3, u4],
+        (1..5).map(|id| Some(format!("user{}", id))).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        db.log,
+        vec!["begin", "load [1, 2, 3, 4]", "savepoint", "release", "commit"]
+    );
+}
@@ -0,0 +1,97 @@
+extern crate transaction;
+
+use transaction::prelude::*;
+use transaction::{cache_insert, cache_invalidate, cached, CacheCtx, CacheLog, SharedCache};
+
+struct Ctx {
+    cache_log: CacheLog,
+}
+
+impl CacheCtx for Ctx {
+    fn cache_log(&mut self) -> &mut CacheLog {
+        &mut self.cache_log
+    }
+}
+
+fn ctx() -> Ctx {
+    Ctx { cache_log: CacheLog::new() }
+}
+
+fn find_user(cache: &SharedCache<i64, String>, id: i64, name: &'static str) -> impl TxFor<Ctx, String, ()> {
+    cached(cache, id, ok(name.to_string()))
+}
+
+#[test]
+fn stale_fill_does_not_overwrite_invalidation() {
+    let users = SharedCache::new();
+    let (mut slow, mut fast) = (ctx(), ctx());
+
+    // `slow` misses and reads the old value
+    find_user(&users, 1, "old").run(&mut slow).unwrap();
+    // `fast` updates the user and invalidates the cache
+    cache_invalidate::<_, _, String, ()>(&users, 1).run(&mut fast).unwrap();
+    fast.cache_log.commit();
+    slow.cache_log.commit();
+    assert_eq!(users.get(&1), None);
+
+    // the next miss fills the cache
+    find_user(&users, 1, "new").run(&mut slow).unwrap();
+    slow.cache_log.commit();
+    assert_eq!(users.get(&1), Some("new".to_string()));
+}
+
+#[test]
+fn stale_fill_does_not_overwrite_insert() {
+    let users = SharedCache::new();
+    let (mut slow, mut fast) = (ctx(), ctx());
+
+    find_user(&users, 1, "old").run(&mut slow).unwrap();
+    cache_insert::<_, _, _, ()>(&users, 1, "new".to_string()).run(&mut fast).unwrap();
+    fast.cache_log.commit();
+    slow.cache_log.commit();
+    assert_eq!(users.get(&1), Some("new".to_string()));
+}
+
+#[test]
+fn fill_after_own_invalidation_is_written() {
+    let users = SharedCache::new();
+    let mut ctx = ctx();
+    let tx = cache_invalidate::<_, _, String, ()>(&users, 1)
+        .and_then(|_| find_user(&users, 1, "mine"));
+    assert_eq!(tx.run(&mut ctx), Ok("mine".to_string()));
+    ctx.cache_log.commit();
+    assert_eq!(users.get(&1), Some("mine".to_string()));
+}
+
+#[test]
+fn stale_fill_does_not_survive_clear() {
+    let users = SharedCache::new();
+    let mut slow = ctx();
+
+    find_user(&users, 1, "old").run(&mut slow).unwrap();
+    users.clear();
+    slow.cache_log.commit();
+    assert_eq!(users.get(&1), None);
+
+    find_user(&users, 1, "new").run(&mut slow).unwrap();
+    slow.cache_log.commit();
+    assert_eq!(users.get(&1), Some("new".to_string()));
+}
+
+#[test]
+fn changes_rolled_back_to_savepoint_are_not_seen() {
+    let users = SharedCache::new();
+    let mut ctx = ctx();
+    let insert = |name: &str| cache_insert::<_, _, _, ()>(&users, 1, name.to_string());
+
+    insert("kept").run(&mut ctx).unwrap();
+    ctx.cache_log.savepoint();
+    insert("dropped").run(&mut ctx).unwrap();
+    cache_invalidate::<_, _, String, ()>(&users, 1).run(&mut ctx).unwrap();
+    assert_eq!(find_user(&users, 1, "read").run(&mut ctx), Ok("read".to_string()));
+    ctx.cache_log.rollback_to_savepoint();
+    assert_eq!(find_user(&users, 1, "read").run(&mut ctx), Ok("kept".to_string()));
+
+    ctx.cache_log.commit();
+    assert_eq!(users.get(&1), Some("kept".to_string()));
+}
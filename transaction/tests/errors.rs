@@ -0,0 +1,100 @@
+#[macro_use]
+extern crate transaction;
+
+use std::error::Error;
+use std::fmt;
+
+use transaction::prelude::*;
+use transaction::AnyTxError;
+
+#[derive(Debug, Clone, PartialEq)]
+struct DbError;
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "db error")
+    }
+}
+
+impl Error for DbError {}
+
+#[derive(Debug, Clone, PartialEq)]
+struct DomainError(&'static str);
+
+impl fmt::Display for DomainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "domain error: {}", self.0)
+    }
+}
+
+impl Error for DomainError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&DbError)
+    }
+}
+
+tx_error! {
+    #[derive(Debug, PartialEq)]
+    enum AppError {
+        Db(DbError),
+        Domain(DomainError),
+    }
+}
+
+#[test]
+fn any_tx_error_wraps_and_downcasts() {
+    let e = AnyTxError::from(DbError);
+    assert_eq!(e.downcast_ref::<DbError>(), Some(&DbError));
+    assert_eq!(e.downcast_ref::<DomainError>(), None);
+    assert_eq!(e.to_string(), "db error");
+    assert!(e.into_inner().downcast::<DbError>().is_ok());
+
+    let e = AnyTxError::new("message");
+    assert_eq!(e.error().to_string(), "message");
+}
+
+#[test]
+fn contexts_are_printed_outermost_first() {
+    let e = AnyTxError::new(DomainError("taken")).context("insert user").context("sign up");
+    assert_eq!(e.contexts(), vec!["sign up", "insert user"]);
+    assert_eq!(e.to_string(), "sign up: insert user: domain error: taken");
+    let debug = format!("{:?}", e);
+    assert!(debug.starts_with("sign up: insert user: domain error: taken\ncaused by: db error"));
+}
+
+#[test]
+fn context_chains_on_transactions() {
+    let tx = err::<(), (), _>(DomainError("taken"))
+        .context("insert user")
+        .and_then(|_| ok(()))
+        .context("sign up");
+    let e = tx.run(&mut ()).unwrap_err();
+    assert_eq!(e.contexts(), vec!["sign up", "insert user"]);
+    assert_eq!(e.downcast_ref::<DomainError>(), Some(&DomainError("taken")));
+
+    let tx = ok::<(), i32, DbError>(1).context("never attached");
+    assert_eq!(tx.run(&mut ()).unwrap(), 1);
+}
+
+#[test]
+fn err_into_converts_to_the_sum_type() {
+    let tx = err::<(), (), _>(DbError)
+        .err_into::<AppError>()
+        .or_else(|e| {
+            assert_eq!(e, AppError::Db(DbError));
+            err(DomainError("fallback")).err_into()
+        });
+    assert_eq!(tx.run(&mut ()), Err(AppError::Domain(DomainError("fallback"))));
+}
+
+#[test]
+fn join_err_into_joins_different_error_types() {
+    let tx = ok::<(), _, DbError>(1).join_err_into::<AppError, _>(ok::<(), _, DomainError>("a"));
+    assert_eq!(tx.run(&mut ()), Ok((1, "a")));
+
+    let tx = ok::<(), i32, DbError>(1).join_err_into::<AppError, _>(err::<(), (), _>(DomainError("b")));
+    assert_eq!(tx.run(&mut ()), Err(AppError::Domain(DomainError("b"))));
+
+    let tx = err::<(), i32, _>(DbError).join_err_into::<AnyTxError, _>(ok::<(), _, DomainError>(()));
+    assert!(tx.run(&mut ()).unwrap_err().downcast_ref::<DbError>().is_some());
+}
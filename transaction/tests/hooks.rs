@@ -0,0 +1,56 @@
+extern crate transaction;
+
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use transaction::prelude::*;
+use transaction::{on_commit, on_rollback, HookCtx, Hooks};
+
+struct Ctx {
+    hooks: Hooks,
+}
+
+impl HookCtx for Ctx {
+    fn hooks(&mut self) -> &mut Hooks {
+        &mut self.hooks
+    }
+}
+
+fn count(calls: &Rc<Cell<i32>>) -> impl Fn() + Clone {
+    let calls = calls.clone();
+    move || calls.set(calls.get() + 1)
+}
+
+#[test]
+fn hooks_after_a_panicking_one_are_called() {
+    let calls = Rc::new(Cell::new(0));
+    let tx = on_commit::<Ctx, _, ()>(count(&calls))
+        .and_then(|_| on_commit(|| panic!("in a hook")))
+        .and_then(|_| on_commit(count(&calls)));
+    let mut ctx = Ctx { hooks: Hooks::new() };
+    tx.run(&mut ctx).unwrap();
+    let ret = panic::catch_unwind(AssertUnwindSafe(|| ctx.hooks.commit()));
+    assert!(ret.is_err());
+    assert_eq!(calls.get(), 2);
+
+    // no hooks are left to be called again
+    ctx.hooks.commit();
+    assert_eq!(calls.get(), 2);
+}
+
+#[test]
+fn rolled_back_savepoints_call_their_hooks() {
+    let (committed, rolled_back) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+    let mut ctx = Ctx { hooks: Hooks::new() };
+    on_commit::<Ctx, _, ()>(count(&committed)).run(&mut ctx).unwrap();
+    ctx.hooks.savepoint();
+    on_commit::<Ctx, _, ()>(count(&committed))
+        .and_then(|_| on_rollback(count(&rolled_back)))
+        .run(&mut ctx)
+        .unwrap();
+    ctx.hooks.rollback_to_savepoint();
+    assert_eq!(rolled_back.get(), 1);
+    ctx.hooks.commit();
+    assert_eq!(committed.get(), 1);
+}
@@ -0,0 +1,102 @@
+extern crate transaction;
+
+use std::rc::Rc;
+use std::thread;
+use transaction::prelude::*;
+use transaction::{chain_send, Loop, SendBoxTx, SyncBoxTx};
+
+// neither `Send` nor `Sync`
+struct Ctx(Rc<i32>);
+
+fn assert_send<T: Send>(_: &T) {}
+fn assert_sync<T: Sync>(_: &T) {}
+
+fn get() -> impl TxFor<Ctx, i32, Rc<()>> + Send + Sync {
+    with_ctx(|ctx: &mut Ctx| Ok(*ctx.0))
+}
+
+#[test]
+fn leaves_are_send_sync() {
+    let txs = (
+        ok::<Ctx, (), Rc<()>>(()),
+        err::<Ctx, Rc<()>, ()>(()),
+        result::<Ctx, (), ()>(Ok(())),
+        lazy::<Ctx, _, (), Rc<()>>(|| Ok(())),
+        get(),
+    );
+    assert_send(&txs);
+    assert_sync(&txs);
+}
+
+#[test]
+fn combinators_are_send_sync() {
+    let tx = get()
+        .then(|r| r)
+        .map(|x| x + 1)
+        .and_then(ok)
+        .map_err(|e| e)
+        .or_else(err)
+        .abort(|_| Rc::new(()))
+        .recover::<Rc<()>, _>(|_| 0)
+        .try_abort(Ok)
+        .try_recover(Err::<i32, _>)
+        .join(get())
+        .join3(get(), get())
+        .join4(get(), get(), get());
+    assert_send(&tx);
+    assert_sync(&tx);
+
+    let tx = get()
+        .err_into::<Rc<()>>()
+        .and_then(|x| if x == 0 {
+            get().branch().first()
+        } else {
+            ok(x).branch().second()
+        })
+        .and_then(|x| match x {
+            0 => get().branch3().first(),
+            1 => get().branch3().second(),
+            _ => ok(x).branch3().third(),
+        })
+        .and_then(|x| match x {
+            0 => get().branch4().first(),
+            1 => get().branch4().second(),
+            2 => get().branch4().third(),
+            _ => ok(x).branch4().fourth(),
+        });
+    assert_send(&tx);
+    assert_sync(&tx);
+
+    let tx = (
+        retry(3, |_| get()),
+        retry_if(3, |_| true, |_| get()),
+        repeat(3, |_| get()),
+        loop_fn(0, |_| get().map(Loop::Break::<i32, _>)),
+        join_all(vec![get(), get()]),
+        get().memo(),
+        get().memo_by(1),
+    );
+    assert_send(&tx);
+    assert_sync(&tx);
+}
+
+#[test]
+fn boxed_tx_can_be_sent() {
+    let tx: SendBoxTx<'static, i32, i32, ()> =
+        with_ctx(|ctx: &mut i32| Ok(*ctx + 1)).boxed_send();
+    let ret = thread::spawn(move || tx.run(&mut 1)).join().unwrap();
+    assert_eq!(ret, Ok(2));
+
+    let tx: SyncBoxTx<'static, i32, i32, ()> = ok(1).boxed_sync();
+    assert_send(&tx);
+    assert_sync(&tx);
+}
+
+#[test]
+fn send_chain_can_be_sent() {
+    let tx = chain_send(with_ctx(|ctx: &mut i32| Ok::<_, ()>(*ctx + 1)))
+        .and_then(|x| ok(x.to_string()))
+        .and_then(|s| ok(s + "!"));
+    let ret = thread::spawn(move || tx.run(&mut 1)).join().unwrap();
+    assert_eq!(ret, Ok("2!".to_string()));
+}